@@ -1,26 +1,125 @@
+use ::config::{Config as LayeredConfig, Environment, Value as ConfigValue, ValueKind};
+use anyhow::anyhow;
 use anyhow::Context;
+use cargo::util::important_paths::find_root_manifest_for_wd;
+use cargo::GlobalContext;
 use colored::Color;
 use colored::ColoredString;
 use colored::Colorize as _;
-use serde::Deserialize;
-use std::collections::HashMap;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env::current_dir;
 use std::hash::DefaultHasher;
 use std::hash::Hash as _;
 use std::hash::Hasher as _;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command as AsyncCommand;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command as AsyncCommand};
 use tokio::signal;
+use tokio::sync::watch;
 use tokio::task::JoinSet;
+use toml_edit::Key;
 
-pub fn parse_command(command: &str) -> (&str, Vec<&str>) {
-    let parts: Vec<&str> = command.split_whitespace().collect();
-    let program = parts.first().unwrap_or(&""); // Safely get the first part or empty string if none
-    let args = parts.get(1..).unwrap_or(&[]).to_vec(); // Get remaining parts as args or empty if none
+use crate::error_msg;
+use crate::graph::cycle_path;
+use crate::krate::{Krate, KrateSource};
+use crate::plugin::hooks::Hooks;
+use crate::release::changelog::DEFAULT_TAG_TEMPLATE;
+use crate::release::{Bump, Propagation, VersioningMode};
 
-    (program, args)
+/// Splits a command string into a program and its arguments, following POSIX shell quoting
+/// rules. No expansion, pipes or redirections happen here: use a `Shell` for that.
+pub fn parse_command(command: &str) -> anyhow::Result<(String, Vec<String>)> {
+    let mut parts = shell_words::split(command)
+        .with_context(|| format!("Failed to parse command '{}'", command))?
+        .into_iter();
+    let program = parts.next().ok_or_else(|| anyhow!("Empty command"))?;
+
+    Ok((program, parts.collect()))
+}
+
+/// Interpreter used to run a command string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Sh,
+    Bash,
+    Cmd,
+    /// Tokenize the command and spawn the program directly, without any shell
+    None,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        if cfg!(windows) {
+            Shell::Cmd
+        } else {
+            Shell::Sh
+        }
+    }
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" => Ok(Shell::Sh),
+            "bash" => Ok(Shell::Bash),
+            "cmd" => Ok(Shell::Cmd),
+            "none" => Ok(Shell::None),
+            _ => Err(anyhow!(
+                "Unknown shell '{}' (expected sh, bash, cmd or none)",
+                s
+            )),
+        }
+    }
+}
+
+impl Shell {
+    /// Builds the process running `command` through this shell.
+    pub fn process(&self, command: &str) -> anyhow::Result<std::process::Command> {
+        let (program, args) = match self {
+            Shell::Sh => (
+                "sh".to_string(),
+                vec!["-c".to_string(), command.to_string()],
+            ),
+            Shell::Bash => (
+                "bash".to_string(),
+                vec!["-c".to_string(), command.to_string()],
+            ),
+            Shell::Cmd => return Ok(cmd_process(command)),
+            Shell::None => parse_command(command)?,
+        };
+
+        let mut process = std::process::Command::new(program);
+        process.args(args);
+        Ok(process)
+    }
+}
+
+/// `cmd.exe` has its own quoting rules: the command is passed verbatim after `/C`, the
+/// standard argument escaping would break embedded quotes and `&&`.
+#[cfg(windows)]
+fn cmd_process(command: &str) -> std::process::Command {
+    use std::os::windows::process::CommandExt as _;
+
+    let mut process = std::process::Command::new("cmd");
+    process.arg("/C").raw_arg(command);
+    process
+}
+
+#[cfg(not(windows))]
+fn cmd_process(command: &str) -> std::process::Command {
+    let mut process = std::process::Command::new("cmd");
+    process.args(["/C", command]);
+    process
 }
 
 /// Generates a hash value for a given string.
@@ -47,12 +146,74 @@ fn colorize_key(key: &str) -> ColoredString {
 
 #[derive(Deserialize, Debug)]
 struct RawConfig {
+    #[serde(default)]
     cmd: HashMap<String, serde_json::Value>, // Use serde_json::Value for flexible parsing
+    #[serde(default)]
+    release: ReleaseConfig,
+    #[serde(default)]
+    hooks: BTreeMap<String, Vec<String>>,
 }
 
-#[derive(Debug, Clone)]
+/// Name of the configuration file, looked up from the current directory up to the workspace
+/// root.
+pub const CONFIG_FILE: &str = "Peniche.toml";
+
+#[derive(Debug, Clone, Default)]
 pub struct Config {
+    /// File the configuration was read from, `None` when no configuration file was found
+    pub path: Option<PathBuf>,
     pub cmd: HashMap<String, Command>,
+    pub release: ReleaseConfig,
+    /// Hook point to its `cmd:<name>` and `plugin:<name>` actions
+    pub hooks: BTreeMap<String, Vec<String>>,
+    /// Every value set by a configuration layer, sorted by key
+    pub entries: Vec<ConfigEntry>,
+}
+
+/// A configuration value and the layer it was read from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigEntry {
+    /// Dotted key, such as `release.branch`
+    pub key: String,
+    pub value: String,
+    /// Path of the file, or `the environment`
+    pub origin: Option<String>,
+}
+
+/// `[release]` section of Peniche.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ReleaseConfig {
+    /// Name of the per-crate tags, with `{name}` and `{version}` placeholders
+    pub tag: String,
+    /// Message of the release commit, `{releases}` lists the released crates
+    #[serde(alias = "commit_message")]
+    pub commit_message: String,
+    /// Branch releases are allowed from, any when unset
+    pub branch: Option<String>,
+    /// `fixed` to share one version across the workspace, `independent` otherwise
+    pub mode: VersioningMode,
+    /// Crates that always version together in independent mode, by group name
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// `always`, `breaking` (requirement no longer matching) or `never` release dependents
+    pub propagate: Propagation,
+    /// Minimum bump of the dependents released because of a dependency
+    #[serde(alias = "dependent_bump")]
+    pub dependent_bump: Bump,
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        Self {
+            tag: DEFAULT_TAG_TEMPLATE.to_string(),
+            commit_message: "chore(release): {releases}".to_string(),
+            branch: None,
+            mode: VersioningMode::default(),
+            groups: BTreeMap::new(),
+            propagate: Propagation::default(),
+            dependent_bump: Bump::Patch,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -65,6 +226,8 @@ pub enum Command {
         command: String,
         working_dir: Option<String>,
         env: Option<HashMap<String, String>>, // Optional environment variables
+        #[serde(default)]
+        shell: Shell,
     },
     PlatformSpecific {
         key: String,
@@ -82,128 +245,504 @@ pub struct PlatformCommands {
     pub command: Option<String>,
     pub working_dir: Option<String>,
     pub env: Option<HashMap<String, String>>, // Optional environment variables
+    #[serde(default)]
+    pub depends_on: Vec<String>, // Commands to run before this one
+    #[serde(default)]
+    pub shell: Shell,
+    #[serde(default)]
+    pub foreach: bool, // Run once in every workspace member
+}
+
+/// How a run reacts when one of its commands fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailureMode {
+    /// Kill running commands and skip the remaining ones on the first failure
+    #[default]
+    FailFast,
+    /// Keep running every command that does not depend on a failed one
+    KeepGoing,
+}
+
+/// Options of a `peniche run` invocation.
+#[derive(Debug, Default)]
+pub struct RunOptions<'a> {
+    pub mode: FailureMode,
+    /// Run the requested commands (not their prerequisites) once per crate
+    pub each: bool,
+    /// Workspace members used by `each` and `foreach` commands
+    pub crates: Vec<&'a Krate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandStatus {
+    Success,
+    /// Exited unsuccessfully, with its exit code unless it was killed by a signal
+    Failed(Option<i32>),
+    /// Could not be started at all
+    Error(String),
+    /// Killed because another command failed
+    Cancelled,
+    /// Never started because a prerequisite failed or the run was aborted
+    Skipped,
+}
+
+impl CommandStatus {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, CommandStatus::Failed(_) | CommandStatus::Error(_))
+    }
+
+    fn label(&self) -> ColoredString {
+        match self {
+            CommandStatus::Success => "ok".green(),
+            CommandStatus::Failed(Some(code)) => format!("failed ({})", code).red(),
+            CommandStatus::Failed(None) => "killed".red(),
+            CommandStatus::Error(_) => "error".red(),
+            CommandStatus::Cancelled => "cancelled".yellow(),
+            CommandStatus::Skipped => "skipped".dimmed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandReport {
+    pub name: String,
+    pub status: CommandStatus,
+    pub duration: Option<Duration>,
+}
+
+/// Outcome of every command involved in a run, in completion order.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub commands: Vec<CommandReport>,
+}
+
+impl RunReport {
+    pub fn is_success(&self) -> bool {
+        self.commands
+            .iter()
+            .all(|c| c.status == CommandStatus::Success)
+    }
+
+    /// Exit code of the first failed command, 1 if it has none, 0 when everything succeeded.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_success() {
+            return 0;
+        }
+
+        self.commands
+            .iter()
+            .find_map(|c| match c.status {
+                CommandStatus::Failed(Some(code)) if code != 0 => Some(code),
+                _ => None,
+            })
+            .unwrap_or(1)
+    }
+
+    pub fn print_summary(&self) {
+        let width = self
+            .commands
+            .iter()
+            .map(|c| c.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("COMMAND".len());
+
+        println!();
+        println!(
+            "{:<width$}  {:<12}  {}",
+            "COMMAND".bold(),
+            "STATUS".bold(),
+            "DURATION".bold()
+        );
+        for command in &self.commands {
+            let duration = command
+                .duration
+                .map_or("-".to_string(), |d| format!("{:.2}s", d.as_secs_f64()));
+            println!(
+                "{:<width$}  {:<12}  {}",
+                colorize_key(&command.name),
+                command.status.label(),
+                duration
+            );
+        }
+    }
 }
 
 impl Config {
+    /// Loads the layered configuration, each layer overriding the previous ones:
+    ///
+    /// 1. the user configuration, `~/.config/peniche/config.toml`
+    /// 2. `explicit` when given, else the closest `Peniche.toml` between `cwd` and the
+    ///    workspace root
+    /// 3. the `[cmd]` section of the `Peniche.toml` of workspace members, whose commands are
+    ///    named `<crate>:<command>` and run from the crate directory
+    /// 4. `PENICHE_<SECTION>__<KEY>` environment variables, such as `PENICHE_RELEASE__BRANCH`
+    ///
+    /// Tables of the layers are merged, other values replaced. Every layer is optional, the
+    /// configuration being empty without any of them.
+    pub async fn load(explicit: Option<&Path>, cwd: &Path) -> anyhow::Result<Self> {
+        let layout = workspace_layout(cwd);
+        let path = match explicit {
+            Some(path) => Some(
+                std::fs::canonicalize(path)
+                    .with_context(|| format!("Failed to open configuration file {:?}", path))?,
+            ),
+            None => discover_config(cwd, layout.as_ref()),
+        };
+        let base_dir = match (&path, &layout) {
+            (Some(path), _) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            (None, Some(layout)) => layout.root.clone(),
+            (None, None) => cwd.to_path_buf(),
+        };
+
+        let mut layers = vec![];
+        if let Some(user_path) = user_config_path().filter(|path| path.is_file()) {
+            layers.push(Layer::read(&user_path)?);
+        }
+        if let Some(path) = &path {
+            layers.push(Layer::read(path)?);
+        }
+        layers.push(Layer::environment(std::env::vars())?);
+
+        let mut config = Self::from_layers(&layers, &base_dir)?;
+        config.path = path;
+
+        for (name, dir) in layout.map(|layout| layout.members).unwrap_or_default() {
+            let member_path = dir.join(CONFIG_FILE);
+            if member_path.is_file() && config.path.as_ref() != Some(&member_path) {
+                config.add_member_commands(&name, &dir, &Layer::read(&member_path)?)?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the configuration file at `path` alone, without the other layers. Relative
+    /// working directories of commands are resolved against the directory of the file, where
+    /// commands run by default.
     pub async fn from_file(path: Option<&str>) -> anyhow::Result<Self> {
-        let path = path.unwrap_or("Peniche.toml");
-        let mut file = File::open(path)
-            .await
-            .context("Failed to open configuration file")?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .await
-            .context("Failed to read configuration file")?;
-
-        let raw_config: RawConfig = toml::from_str(&contents)?;
-        let mut commands = HashMap::new();
-
-        for (key, value) in raw_config.cmd {
-            let colored_key = colorize_key(&key);
-
-            match value {
-                serde_json::Value::String(cmd) => {
-                    // Assume no working_dir is specified if only a string is provided
-                    commands.insert(
-                        key.clone(),
-                        Command::Simple {
-                            key,
-                            colored_key,
-                            command: cmd,
-                            working_dir: None,
-                            env: None,
-                        },
-                    );
-                }
-                serde_json::Value::Object(map) => {
-                    let platform_commands = PlatformCommands {
-                        windows: map
-                            .get("windows")
-                            .and_then(|v| v.as_str())
-                            .map(String::from),
-
-                        linux: map.get("linux").and_then(|v| v.as_str()).map(String::from),
-
-                        darwin: map.get("darwin").and_then(|v| v.as_str()).map(String::from),
-
-                        command: map
-                            .get("command")
-                            .and_then(|v| v.as_str())
-                            .map(String::from),
-
-                        working_dir: map
-                            .get("working_dir")
-                            .and_then(|v| v.as_str())
-                            .map(String::from),
-
-                        env: map.get("env").and_then(|v| v.as_object()).map(|obj| {
-                            obj.iter()
-                                .filter_map(|(k, v)| {
-                                    if let Some(value) = v.as_str() {
-                                        Some((k.clone(), value.to_string()))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect::<HashMap<String, String>>()
-                        }),
-                    };
-                    commands.insert(
-                        key.clone(),
-                        Command::PlatformSpecific {
-                            key,
-                            colored_key,
-                            commands: platform_commands,
-                        },
-                    );
-                }
-                _ => return Err(anyhow::anyhow!("Unexpected format in command definition")),
+        let path = path.unwrap_or(CONFIG_FILE);
+        let path = std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to open configuration file {}", path))?;
+
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut config = Self::from_layers(&[Layer::read(&path)?], &base_dir)?;
+        config.path = Some(path);
+
+        Ok(config)
+    }
+
+    fn from_layers(layers: &[Layer], base_dir: &Path) -> anyhow::Result<Self> {
+        let mut merged = toml::Table::new();
+        let mut origins = HashMap::new();
+        for layer in layers {
+            merge_tables(
+                &mut merged,
+                &layer.table,
+                &layer.origin,
+                &mut vec![],
+                &mut origins,
+            );
+        }
+        let merged = toml::Value::Table(merged);
+
+        let mut entries = vec![];
+        collect_entries(
+            &mut vec![],
+            &merged,
+            &|path| origins.get(path).cloned(),
+            &mut entries,
+        );
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let raw_config: RawConfig = merged.try_into().context("Invalid configuration")?;
+
+        Ok(Config {
+            path: None,
+            cmd: parse_commands(raw_config.cmd, base_dir, None)?,
+            release: raw_config.release,
+            hooks: raw_config.hooks,
+            entries,
+        })
+    }
+
+    /// Adds the `[cmd]` section of a member's Peniche.toml, its commands being scoped to the
+    /// member. The other sections only apply to the whole workspace.
+    fn add_member_commands(
+        &mut self,
+        krate: &str,
+        dir: &Path,
+        layer: &Layer,
+    ) -> anyhow::Result<()> {
+        let raw_config = layer.raw_config()?;
+        self.cmd
+            .extend(parse_commands(raw_config.cmd, dir, Some(krate))?);
+
+        if let Some(toml::Value::Table(commands)) = layer.table.get("cmd") {
+            for (name, value) in commands {
+                let mut path = vec!["cmd".to_string(), scoped_key(krate, name)];
+                collect_entries(
+                    &mut path,
+                    value,
+                    &|_| Some(layer.origin.clone()),
+                    &mut self.entries,
+                );
             }
         }
+        self.entries.sort_by(|a, b| a.key.cmp(&b.key));
 
-        Ok(Config { cmd: commands })
+        Ok(())
     }
 
-    pub async fn execute_commands_in_parallel(&self, cmd_names: Vec<String>) {
-        let mut join_set = JoinSet::new();
+    /// Lifecycle hooks of the `[hooks]` section, plugins being looked up from `workspace_root`.
+    pub fn hooks(&self, workspace_root: Option<PathBuf>) -> anyhow::Result<Hooks> {
+        Hooks::new(&self.hooks, &self.cmd, workspace_root)
+    }
+
+    /// Builds the graph of the requested commands and all of their transitive prerequisites.
+    /// Edges point from a prerequisite to the command that depends on it.
+    pub fn command_graph(&self, cmd_names: &[String]) -> anyhow::Result<DiGraph<String, ()>> {
+        let mut graph = DiGraph::new();
+        let mut nodes: HashMap<String, NodeIndex> = HashMap::new();
+        let mut stack: Vec<(String, Option<String>)> = cmd_names
+            .iter()
+            .rev()
+            .map(|name| (name.clone(), None))
+            .collect();
+
+        while let Some((name, required_by)) = stack.pop() {
+            if nodes.contains_key(&name) {
+                continue;
+            }
+
+            let command = self.cmd.get(&name).ok_or_else(|| match &required_by {
+                Some(parent) => anyhow!(
+                    "Command '{}' (required by '{}') not found in configuration",
+                    name,
+                    parent
+                ),
+                None => anyhow!("Command '{}' not found in configuration", name),
+            })?;
 
-        for name in cmd_names {
-            if let Some(command) = self.cmd.get(&name).cloned() {
-                join_set.spawn(tokio::spawn(async move {
-                    let _ = command.stream_command().await.unwrap();
-                }));
+            nodes.insert(name.clone(), graph.add_node(name.clone()));
+            for dep in command.depends_on().iter().rev() {
+                stack.push((dep.clone(), Some(name.clone())));
+            }
+        }
+
+        for (name, &idx) in &nodes {
+            for dep in self.cmd[name].depends_on() {
+                graph.update_edge(nodes[dep], idx, ());
+            }
+        }
+
+        let cycles: Vec<String> = tarjan_scc(&graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || graph.contains_edge(scc[0], scc[0]))
+            .map(|scc| cycle_path(&graph, &scc, Direction::Incoming).join(" -> "))
+            .collect();
+
+        if !cycles.is_empty() {
+            return Err(anyhow!(
+                "Dependency cycle detected in [cmd] (a -> b means 'a depends on b'):\n  {}",
+                cycles.join("\n  ")
+            ));
+        }
+
+        Ok(graph)
+    }
+
+    /// Runs the requested commands, starting each one as soon as all of its prerequisites
+    /// succeeded. Independent commands run concurrently.
+    pub async fn execute_commands_in_parallel(
+        &self,
+        cmd_names: Vec<String>,
+        options: RunOptions<'_>,
+    ) -> anyhow::Result<RunReport> {
+        let graph = self.command_graph(&cmd_names)?;
+
+        // Expand per-crate commands up front so template errors abort before anything runs
+        let mut instances: HashMap<NodeIndex, Vec<Command>> = HashMap::new();
+        for idx in graph.node_indices() {
+            let command = &self.cmd[&graph[idx]];
+            let per_crate = command.foreach() || (options.each && cmd_names.contains(&graph[idx]));
+
+            let runs = if per_crate {
+                options
+                    .crates
+                    .iter()
+                    .map(|krate| command.for_krate(krate))
+                    .collect::<anyhow::Result<Vec<_>>>()?
             } else {
-                eprintln!("Command '{}' not found in configuration", name);
+                vec![command.clone()]
+            };
+            instances.insert(idx, runs);
+        }
+
+        // Number of prerequisites left before each command can start
+        let mut pending: HashMap<NodeIndex, usize> = graph
+            .node_indices()
+            .map(|idx| {
+                let prerequisites = graph.neighbors_directed(idx, Direction::Incoming).count();
+                (idx, prerequisites)
+            })
+            .collect();
+
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let mut join_set = JoinSet::new();
+        let mut report = RunReport::default();
+        // Once Ctrl-C is pressed, running commands shut down on their own and nothing new starts
+        let mut interrupted = false;
+
+        loop {
+            let ready: Vec<NodeIndex> = pending
+                .iter()
+                .filter(|(_, &remaining)| remaining == 0 && !interrupted && !*cancel_tx.borrow())
+                .map(|(&idx, _)| idx)
+                .collect();
+
+            for idx in ready {
+                pending.remove(&idx);
+                let name = graph[idx].clone();
+                let runs = instances.remove(&idx).unwrap_or_default();
+                let cancel_rx = cancel_rx.clone();
+
+                join_set.spawn(async move {
+                    let mut join_set = JoinSet::new();
+                    for command in runs {
+                        let name = match &command {
+                            Command::Simple { key, .. } if key != &name => {
+                                format!("{} ({})", name, key)
+                            }
+                            _ => name.clone(),
+                        };
+                        join_set.spawn(run_command(name, command, cancel_rx.clone()));
+                    }
+
+                    let mut reports = vec![];
+                    while let Some(joined) = join_set.join_next().await {
+                        reports.push(joined?);
+                    }
+                    Ok::<_, tokio::task::JoinError>((idx, reports))
+                });
+            }
+
+            let joined = tokio::select! {
+                joined = join_set.join_next() => joined,
+                _ = signal::ctrl_c(), if !interrupted => {
+                    interrupted = true;
+                    continue;
+                }
+            };
+
+            let Some(joined) = joined else {
+                break;
+            };
+
+            let (idx, reports) = joined??;
+            let mut failed = false;
+
+            for command in &reports {
+                match &command.status {
+                    CommandStatus::Failed(code) => {
+                        let code = code.map_or("signal".to_string(), |c| c.to_string());
+                        error_msg!("Command '{}' failed (exit code: {})", command.name, code);
+                    }
+                    CommandStatus::Error(err) => {
+                        error_msg!("Command '{}' could not be run: {}", command.name, err);
+                    }
+                    _ => {}
+                }
+                failed |= command.status != CommandStatus::Success;
+            }
+
+            if !failed {
+                for dependent in graph.neighbors_directed(idx, Direction::Outgoing) {
+                    if let Some(remaining) = pending.get_mut(&dependent) {
+                        *remaining -= 1;
+                    }
+                }
             }
+
+            if reports.iter().any(|c| c.status.is_failure())
+                && options.mode == FailureMode::FailFast
+            {
+                cancel_tx.send_replace(true);
+            }
+
+            report.commands.extend(reports);
+        }
+
+        // Anything still pending had a prerequisite that failed or the run was aborted
+        let mut skipped: Vec<&String> = pending.keys().map(|&idx| &graph[idx]).collect();
+        skipped.sort();
+        for name in skipped {
+            report.commands.push(CommandReport {
+                name: name.clone(),
+                status: CommandStatus::Skipped,
+                duration: None,
+            });
         }
 
-        while let Some(_) = join_set.join_next().await {}
+        Ok(report)
+    }
+}
+
+/// Runs a single command until it exits or the run gets cancelled.
+async fn run_command(
+    name: String,
+    command: Command,
+    mut cancel_rx: watch::Receiver<bool>,
+) -> CommandReport {
+    let started = Instant::now();
+    // Dropping the command future kills the child (kill_on_drop)
+    let status = tokio::select! {
+        result = command.stream_command() => match result {
+            Ok(exit) if exit.success() => CommandStatus::Success,
+            Ok(exit) => CommandStatus::Failed(exit.code()),
+            Err(err) => CommandStatus::Error(format!("{:#}", err)),
+        },
+        _ = cancel_rx.wait_for(|&cancelled| cancelled) => CommandStatus::Cancelled,
+    };
+
+    CommandReport {
+        name,
+        status,
+        duration: Some(started.elapsed()),
     }
 }
 
 impl Command {
-    pub async fn stream_command(&self) -> anyhow::Result<()> {
-        let (key, command, working_dir, env_vars) = match self {
-            Command::Simple {
-                key: _,
-                colored_key,
-                command,
-                working_dir,
-                env,
-            } => (
-                colored_key.clone().bold(),
-                command,
-                working_dir.clone(),
-                env,
-            ),
+    pub fn key(&self) -> &str {
+        match self {
+            Command::Simple { key, .. } => key,
+            Command::PlatformSpecific { key, .. } => key,
+        }
+    }
+
+    /// Names of the commands that must complete successfully before this one starts.
+    pub fn depends_on(&self) -> &[String] {
+        match self {
+            Command::Simple { .. } => &[],
+            Command::PlatformSpecific { commands, .. } => &commands.depends_on,
+        }
+    }
+
+    /// Whether the command runs once in every workspace member.
+    pub fn foreach(&self) -> bool {
+        match self {
+            Command::Simple { .. } => false,
+            Command::PlatformSpecific { commands, .. } => commands.foreach,
+        }
+    }
 
-            Command::PlatformSpecific {
-                key: _,
-                colored_key,
-                commands,
-            } => {
+    /// Command string to run on the current platform.
+    pub fn platform_command(&self) -> anyhow::Result<&str> {
+        match self {
+            Command::Simple { command, .. } => Ok(command),
+            Command::PlatformSpecific { key, commands, .. } => {
                 let os_type = std::env::consts::OS;
-                let command = match os_type {
+                match os_type {
                     "windows" => &commands.windows,
                     "linux" => &commands.linux,
                     "darwin" => &commands.darwin,
@@ -211,80 +750,836 @@ impl Command {
                 }
                 .as_deref()
                 .or(commands.command.as_deref())
-                .unwrap_or_default()
-                .to_string();
+                .ok_or_else(|| anyhow!("Command '{}' has nothing to run on {}", key, os_type))
+            }
+        }
+    }
+
+    /// Instantiates the command for a workspace member: `{name}`, `{path}` and `{version}`
+    /// are replaced in the command string, which runs from the crate directory.
+    pub fn for_krate(&self, krate: &Krate) -> anyhow::Result<Command> {
+        let KrateSource::Path(path) = &krate.path else {
+            return Err(anyhow!("Crate '{}' is not a workspace member", krate.name));
+        };
+
+        let path = path.to_string_lossy();
+        let command = self
+            .platform_command()?
+            .replace("{name}", &krate.name)
+            .replace("{path}", &path)
+            .replace("{version}", &krate.version);
+
+        let (env, shell) = match self {
+            Command::Simple { env, shell, .. } => (env.clone(), *shell),
+            Command::PlatformSpecific { commands, .. } => (commands.env.clone(), commands.shell),
+        };
+
+        Ok(Command::Simple {
+            key: krate.name.clone(),
+            colored_key: colorize_key(&krate.name),
+            command,
+            working_dir: Some(path.to_string()),
+            env,
+            shell,
+        })
+    }
+
+    /// Builds the process for the current platform, with its shell, working directory and
+    /// environment applied.
+    pub fn process(&self) -> anyhow::Result<std::process::Command> {
+        let command = self.platform_command()?;
+        let (working_dir, env_vars, shell) = match self {
+            Command::Simple {
+                working_dir,
+                env,
+                shell,
+                ..
+            } => (working_dir.clone(), env, *shell),
 
+            Command::PlatformSpecific { commands, .. } => {
                 let wd = commands
                     .working_dir
                     .clone()
                     .unwrap_or(current_dir()?.to_string_lossy().to_string());
 
-                (
-                    colored_key.clone().bold(),
-                    &command.clone(),
-                    Some(wd),
-                    &commands.env.clone(),
-                )
+                (Some(wd), &commands.env, commands.shell)
             }
         };
 
-        let (program, args) = parse_command(command);
-
-        let mut cmd = AsyncCommand::new(program);
+        let mut process = shell.process(command)?;
         if let Some(working_dir) = working_dir {
-            cmd.current_dir(working_dir);
+            process.current_dir(working_dir);
         }
 
         if let Some(env_vars) = env_vars {
-            for (key, value) in env_vars {
-                cmd.env(key, value);
-            }
+            process.envs(env_vars);
         }
 
-        if args.len() > 0 {
-            cmd.args(args);
-        }
+        Ok(process)
+    }
 
-        let mut child = cmd
+    /// Runs the command, forwarding its stdout and stderr line by line (prefixed with the
+    /// command key) until both streams reach EOF, and returns its exit status.
+    ///
+    /// On Unix the child runs in its own process group: Ctrl-C is forwarded to the whole
+    /// group, which gets killed if it is still alive after `SHUTDOWN_GRACE_PERIOD`.
+    pub async fn stream_command(&self) -> anyhow::Result<ExitStatus> {
+        let key = match self {
+            Command::Simple { colored_key, .. } => colored_key.clone().bold(),
+            Command::PlatformSpecific { colored_key, .. } => colored_key.clone().bold(),
+        };
+
+        let mut process = self.process()?;
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut process, 0);
+
+        let mut child = AsyncCommand::from(process)
             .kill_on_drop(true)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
             .spawn()?;
 
-        let stdout = BufReader::new(child.stdout.take().unwrap());
-        let stderr = BufReader::new(child.stderr.take().unwrap());
-
-        let mut stdout_lines = stdout.lines();
-        let mut stderr_lines = stderr.lines();
+        // Kills the whole group if this future is dropped (e.g. a fail-fast cancellation)
+        let group = ProcessGroup::new(&child);
 
         let tag_key = format!("{}{}{}", "[".dimmed(), key.dimmed(), "]".dimmed());
 
-        let ctrl_c = signal::ctrl_c();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let output = async {
+            tokio::join!(
+                pump_lines(stdout, &tag_key, OutputStream::Stdout),
+                pump_lines(stderr, &tag_key, OutputStream::Stderr),
+            )
+        };
+        tokio::pin!(output);
 
         tokio::select! {
-            _ = async {
-                while let Some(line) = stdout_lines.next_line().await? {
-                    println!("{} {}", tag_key, line);
-                }
-                Ok::<(), anyhow::Error>(())
-            } => {},
+            _ = &mut output => {},
 
-            _ = async {
-                while let Some(line) = stderr_lines.next_line().await? {
-                    eprintln!("{} {}", tag_key, line);
-                }
-                Ok::<(), anyhow::Error>(())
-            } => {},
+            _ = signal::ctrl_c() => {
+                group.terminate(&mut child).await;
+                println!("{} {}", tag_key, "Process was stopped due to Ctrl-C".bold());
+                // Flush whatever the process wrote while shutting down
+                let _ = tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, &mut output).await;
+            },
+        }
+
+        let status = child.wait().await?; // Ensure the child process exits cleanly
+        group.release();
+
+        Ok(status)
+    }
+}
+
+/// How long a process group gets to exit after Ctrl-C before being killed.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Forwards every line of `reader` until EOF. Lines are decoded lossily so non UTF-8
+/// output never interrupts the stream.
+async fn pump_lines<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    tag_key: &str,
+    stream: OutputStream,
+) -> std::io::Result<()> {
+    let Some(reader) = reader else {
+        return Ok(());
+    };
+
+    let mut reader = BufReader::new(reader);
+    let mut buffer = Vec::new();
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer).await? == 0 {
+            return Ok(());
+        }
+
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end_matches(['\n', '\r']);
+        match stream {
+            OutputStream::Stdout => println!("{} {}", tag_key, line),
+            OutputStream::Stderr => eprintln!("{} {}", tag_key, line),
+        }
+    }
+}
+
+/// Process group of a spawned command, killed on drop unless released.
+struct ProcessGroup {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    id: Option<u32>,
+}
+
+impl ProcessGroup {
+    fn new(child: &Child) -> Self {
+        Self { id: child.id() }
+    }
+
+    /// Asks the group to stop, then kills it once the grace period is over.
+    async fn terminate(&self, child: &mut Child) {
+        #[cfg(unix)]
+        if let Some(id) = self.id {
+            self.signal(id, libc::SIGINT);
+            if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, child.wait())
+                .await
+                .is_ok()
+            {
+                return;
+            }
+            self.signal(id, libc::SIGKILL);
+        }
+
+        let _ = child.kill().await;
+    }
+
+    /// Forgets the group once the child has been reaped, so its id is never signaled again.
+    fn release(mut self) {
+        self.id = None;
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, id: u32, signal: libc::c_int) {
+        // SAFETY: killpg has no memory safety requirements, failures are reported through errno
+        unsafe {
+            libc::killpg(id as libc::pid_t, signal);
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(id) = self.id {
+            self.signal(id, libc::SIGKILL);
+        }
+    }
+}
+
+/// Name of a command declared in the Peniche.toml of a member.
+pub fn scoped_key(krate: &str, name: &str) -> String {
+    format!("{}:{}", krate, name)
+}
+
+/// Builds the commands of a `[cmd]` section. With a `scope`, commands are named after the
+/// crate and their prerequisites declared in the same section too.
+fn parse_commands(
+    raw_cmd: HashMap<String, serde_json::Value>,
+    base_dir: &Path,
+    scope: Option<&str>,
+) -> anyhow::Result<HashMap<String, Command>> {
+    let names: HashSet<String> = raw_cmd.keys().cloned().collect();
+    let working_dir = |dir: Option<&str>| -> String {
+        match dir {
+            Some(dir) => base_dir.join(dir),
+            None => base_dir.to_path_buf(),
+        }
+        .to_string_lossy()
+        .to_string()
+    };
+    let mut commands = HashMap::new();
+
+    for (key, value) in raw_cmd {
+        let key = match scope {
+            Some(krate) => scoped_key(krate, &key),
+            None => key,
+        };
+        let colored_key = colorize_key(&key);
+
+        match value {
+            serde_json::Value::String(cmd) => {
+                // Plain strings run from the directory of the configuration file
+                commands.insert(
+                    key.clone(),
+                    Command::Simple {
+                        key,
+                        colored_key,
+                        command: cmd,
+                        working_dir: Some(working_dir(None)),
+                        env: None,
+                        shell: Shell::default(),
+                    },
+                );
+            }
+            serde_json::Value::Object(map) => {
+                let platform_commands = PlatformCommands {
+                    windows: map
+                        .get("windows")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+
+                    linux: map.get("linux").and_then(|v| v.as_str()).map(String::from),
+
+                    darwin: map.get("darwin").and_then(|v| v.as_str()).map(String::from),
+
+                    command: map
+                        .get("command")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+
+                    working_dir: Some(working_dir(map.get("working_dir").and_then(|v| v.as_str()))),
+
+                    env: map.get("env").and_then(|v| v.as_object()).map(|obj| {
+                        obj.iter()
+                            .filter_map(|(k, v)| {
+                                if let Some(value) = v.as_str() {
+                                    Some((k.clone(), value.to_string()))
+                                } else {
+                                    None
+                                }
+                            })
+                            .collect::<HashMap<String, String>>()
+                    }),
+
+                    depends_on: match map.get("depends_on") {
+                        None => vec![],
+                        Some(serde_json::Value::Array(deps)) => deps
+                            .iter()
+                            .map(|v| {
+                                v.as_str()
+                                    .map(|dep| match scope {
+                                        Some(krate) if names.contains(dep) => {
+                                            scoped_key(krate, dep)
+                                        }
+                                        _ => dep.to_string(),
+                                    })
+                                    .ok_or_else(|| {
+                                        anyhow!("Invalid 'depends_on' entry in command '{}'", key)
+                                    })
+                            })
+                            .collect::<anyhow::Result<Vec<String>>>()?,
+                        Some(_) => {
+                            return Err(anyhow!(
+                                "'depends_on' of command '{}' must be an array of command names",
+                                key
+                            ))
+                        }
+                    },
+
+                    shell: match map.get("shell") {
+                        None => Shell::default(),
+                        Some(serde_json::Value::String(shell)) => match shell
+                            .parse()
+                            .with_context(|| format!("Invalid shell of command '{}'", key))?
+                        {
+                            Shell::Cmd if !cfg!(windows) => {
+                                return Err(anyhow!(
+                                    "Command '{}' uses the cmd shell, which is only available on Windows",
+                                    key
+                                ))
+                            }
+                            shell => shell,
+                        },
+                        Some(_) => {
+                            return Err(anyhow!("'shell' of command '{}' must be a string", key))
+                        }
+                    },
+
+                    foreach: match map.get("foreach") {
+                        None => false,
+                        Some(serde_json::Value::Bool(foreach)) => *foreach,
+                        Some(_) => {
+                            return Err(anyhow!("'foreach' of command '{}' must be a boolean", key))
+                        }
+                    },
+                };
+                commands.insert(
+                    key.clone(),
+                    Command::PlatformSpecific {
+                        key,
+                        colored_key,
+                        commands: platform_commands,
+                    },
+                );
+            }
+            _ => return Err(anyhow::anyhow!("Unexpected format in command definition")),
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Closest `Peniche.toml` from `cwd` up to the workspace root, or in `cwd` only outside of a
+/// workspace. The files of members are layers of their own and are skipped.
+fn discover_config(cwd: &Path, layout: Option<&WorkspaceLayout>) -> Option<PathBuf> {
+    let root = layout.map(|layout| layout.root.as_path());
+    for dir in cwd.ancestors() {
+        let candidate = dir.join(CONFIG_FILE);
+        let is_member = layout.is_some_and(|layout| {
+            Some(dir) != root && layout.members.iter().any(|(_, member)| member == dir)
+        });
+        if candidate.is_file() && !is_member {
+            return Some(candidate);
+        }
+        match root {
+            Some(root) if dir.starts_with(root) && dir != root => continue,
+            _ => break,
+        }
+    }
+
+    None
+}
+
+/// `~/.config/peniche/config.toml`, or under `$XDG_CONFIG_HOME` when set.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join("peniche").join("config.toml"))
+}
+
+struct WorkspaceLayout {
+    root: PathBuf,
+    /// Name and directory of the members
+    members: Vec<(String, PathBuf)>,
+}
+
+fn workspace_layout(cwd: &Path) -> Option<WorkspaceLayout> {
+    let manifest_path = find_root_manifest_for_wd(cwd).ok()?;
+    let gctx = GlobalContext::default().ok()?;
+    let ws = cargo::core::Workspace::new(&manifest_path, &gctx).ok()?;
 
-            _ = ctrl_c => {
-                if let Ok(_) = child.kill().await {
-                    println!("{} {}", tag_key, "Process was killed due to Ctrl-C".bold());
+    Some(WorkspaceLayout {
+        root: ws.root().to_path_buf(),
+        members: ws
+            .members()
+            .map(|package| (package.name().to_string(), package.root().to_path_buf()))
+            .collect(),
+    })
+}
+
+/// Origin of the values set through environment variables.
+const ENVIRONMENT_ORIGIN: &str = "the environment";
+
+/// A configuration file or the environment overrides, as a TOML table.
+struct Layer {
+    /// Path of the file, or `the environment`
+    origin: String,
+    table: toml::Table,
+}
+
+impl Layer {
+    /// Reads a configuration file, which must be valid on its own.
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {:?}", path))?;
+        let table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse configuration file {:?}", path))?;
+
+        let layer = Self {
+            origin: path.display().to_string(),
+            table,
+        };
+        layer
+            .raw_config()
+            .with_context(|| format!("Invalid configuration file {:?}", path))?;
+        Ok(layer)
+    }
+
+    /// `PENICHE_<SECTION>__<KEY>` variables among `vars`, keys being lowercased. Variables
+    /// without a section, such as `PENICHE_CONFIG` or the ones given to plugins, are not
+    /// configuration values.
+    fn environment(vars: impl IntoIterator<Item = (String, String)>) -> anyhow::Result<Self> {
+        let vars = vars
+            .into_iter()
+            .filter(|(key, _)| {
+                key.strip_prefix("PENICHE_")
+                    .is_some_and(|key| key.contains("__"))
+            })
+            .collect();
+        let overrides = LayeredConfig::builder()
+            .add_source(
+                Environment::with_prefix("PENICHE")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .source(Some(vars)),
+            )
+            .build()
+            .context("Failed to read the configuration from the environment")?;
+
+        let mut table = match toml_value(overrides.cache) {
+            Some(toml::Value::Table(table)) => table,
+            _ => toml::Table::new(),
+        };
+        // Values are kept as strings, `foreach` is the only key of another type
+        if let Some(toml::Value::Table(commands)) = table.get_mut("cmd") {
+            for command in commands.values_mut() {
+                let Some(foreach) = command.get_mut("foreach") else {
+                    continue;
+                };
+                if let Some(parsed) = foreach.as_str().and_then(|value| value.parse().ok()) {
+                    *foreach = toml::Value::Boolean(parsed);
                 }
-            },
+            }
         }
 
-        let _ = child.wait().await; // Ensure the child process exits cleanly
+        Ok(Self {
+            origin: ENVIRONMENT_ORIGIN.to_string(),
+            table,
+        })
+    }
 
-        Ok(())
+    fn raw_config(&self) -> anyhow::Result<RawConfig> {
+        Ok(toml::Value::Table(self.table.clone()).try_into()?)
+    }
+}
+
+/// Merges `layer` into `merged`, tables being merged and other values replaced. The origin of
+/// every value set by the layer is recorded under its path.
+fn merge_tables(
+    merged: &mut toml::Table,
+    layer: &toml::Table,
+    origin: &str,
+    path: &mut Vec<String>,
+    origins: &mut HashMap<Vec<String>, String>,
+) {
+    for (key, value) in layer {
+        path.push(key.clone());
+        match (value, merged.get_mut(key)) {
+            (toml::Value::Table(table), Some(toml::Value::Table(existing))) => {
+                merge_tables(existing, table, origin, path, origins);
+            }
+            (toml::Value::Table(table), _) => {
+                let mut existing = toml::Table::new();
+                merge_tables(&mut existing, table, origin, path, origins);
+                merged.insert(key.clone(), toml::Value::Table(existing));
+            }
+            (value, _) => {
+                origins.insert(path.clone(), origin.to_string());
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        path.pop();
+    }
+}
+
+/// TOML counterpart of a value read by the `config` crate, `None` for its null values.
+fn toml_value(value: ConfigValue) -> Option<toml::Value> {
+    Some(match value.kind {
+        ValueKind::Nil => return None,
+        ValueKind::Boolean(value) => toml::Value::Boolean(value),
+        ValueKind::I64(value) => toml::Value::Integer(value),
+        ValueKind::I128(value) => integer_value(i64::try_from(value), value),
+        ValueKind::U64(value) => integer_value(i64::try_from(value), value),
+        ValueKind::U128(value) => integer_value(i64::try_from(value), value),
+        ValueKind::Float(value) => toml::Value::Float(value),
+        ValueKind::String(value) => toml::Value::String(value),
+        ValueKind::Table(table) => toml::Value::Table(
+            table
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, toml_value(value)?)))
+                .collect(),
+        ),
+        ValueKind::Array(items) => {
+            toml::Value::Array(items.into_iter().filter_map(toml_value).collect())
+        }
+    })
+}
+
+/// Integers out of the TOML range are kept as strings.
+fn integer_value<E>(value: Result<i64, E>, original: impl ToString) -> toml::Value {
+    match value {
+        Ok(value) => toml::Value::Integer(value),
+        Err(_) => toml::Value::String(original.to_string()),
+    }
+}
+
+/// Flattens the leaves of `value` into `entries`, tables becoming dotted keys.
+fn collect_entries(
+    path: &mut Vec<String>,
+    value: &toml::Value,
+    origin: &dyn Fn(&[String]) -> Option<String>,
+    entries: &mut Vec<ConfigEntry>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key.clone());
+                collect_entries(path, value, origin, entries);
+                path.pop();
+            }
+        }
+        _ => entries.push(ConfigEntry {
+            key: path
+                .iter()
+                .map(|segment| Key::new(segment.as_str()).to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+            value: value.to_string(),
+            origin: origin(path),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn try_config(content: &str) -> anyhow::Result<Config> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Peniche.toml");
+        std::fs::write(&path, content).unwrap();
+        Config::from_file(path.to_str()).await
+    }
+
+    async fn config(content: &str) -> Config {
+        try_config(content).await.unwrap()
+    }
+
+    fn layer(origin: &str, content: &str) -> Layer {
+        Layer {
+            origin: origin.to_string(),
+            table: toml::from_str(content).unwrap(),
+        }
+    }
+
+    fn origin<'a>(config: &'a Config, key: &str) -> Option<&'a str> {
+        let entry = config.entries.iter().find(|entry| entry.key == key);
+        entry.and_then(|entry| entry.origin.as_deref())
+    }
+
+    fn names(graph: &DiGraph<String, ()>) -> Vec<&str> {
+        let mut names: Vec<&str> = graph.node_weights().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn command_graph_includes_prerequisites() {
+        let config = config(
+            r#"
+[cmd]
+build = "cargo build"
+migrate = { command = "migrate", depends_on = ["build"] }
+start = { command = "serve", depends_on = ["build", "migrate"] }
+lint = "cargo clippy"
+"#,
+        )
+        .await;
+
+        let graph = config.command_graph(&["start".to_string()]).unwrap();
+        assert_eq!(names(&graph), ["build", "migrate", "start"]);
+        assert_eq!(graph.edge_count(), 3);
+
+        let order: Vec<&str> = petgraph::algo::toposort(&graph, None)
+            .unwrap()
+            .into_iter()
+            .map(|idx| graph[idx].as_str())
+            .collect();
+        assert_eq!(order, ["build", "migrate", "start"]);
+    }
+
+    #[tokio::test]
+    async fn command_graph_reports_cycles() {
+        let config = config(
+            r#"
+[cmd]
+a = { command = "a", depends_on = ["b"] }
+b = { command = "b", depends_on = ["c"] }
+c = { command = "c", depends_on = ["a"] }
+self = { command = "self", depends_on = ["self"] }
+"#,
+        )
+        .await;
+
+        let err = config.command_graph(&["a".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+        for name in ["a", "b", "c"] {
+            assert!(err.to_string().contains(name), "{}", err);
+        }
+
+        let err = config.command_graph(&["self".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("self -> self"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn command_graph_reports_unknown_prerequisites() {
+        let config = config(
+            r#"
+[cmd]
+start = { command = "serve", depends_on = ["build"] }
+"#,
+        )
+        .await;
+
+        let err = config.command_graph(&["start".to_string()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command 'build' (required by 'start') not found in configuration"
+        );
+        assert!(config.command_graph(&["stop".to_string()]).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn cmd_shell_is_rejected_outside_of_windows() {
+        let err = try_config(
+            r#"
+[cmd]
+dir = { command = "dir", shell = "cmd" }
+"#,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("only available on Windows"),
+            "{:#}",
+            err
+        );
+
+        let config = config(
+            r#"
+[cmd]
+list = { command = "ls", shell = "sh" }
+"#,
+        )
+        .await;
+        assert!(config.cmd.contains_key("list"));
+    }
+
+    #[test]
+    fn layers_keep_the_case_of_keys() {
+        let workspace = layer(
+            "Peniche.toml",
+            r#"
+[cmd]
+buildDocs = "cargo doc"
+serveDocs = { command = "serve", depends_on = ["buildDocs"], env = { RUST_LOG = "debug" } }
+
+[release.groups]
+Runtime = ["runtime-core", "runtime-macros"]
+"#,
+        );
+        let config = Config::from_layers(&[workspace], Path::new("/ws")).unwrap();
+
+        let Some(Command::PlatformSpecific { commands, .. }) = config.cmd.get("serveDocs") else {
+            panic!("serveDocs not found in {:?}", config.cmd.keys());
+        };
+        let env = commands.env.as_ref().unwrap();
+        assert_eq!(env.get("RUST_LOG").map(String::as_str), Some("debug"));
+
+        let graph = config.command_graph(&["serveDocs".to_string()]).unwrap();
+        assert_eq!(names(&graph), ["buildDocs", "serveDocs"]);
+        assert!(config.release.groups.contains_key("Runtime"));
+        assert!(config
+            .entries
+            .iter()
+            .any(|entry| entry.key == "cmd.serveDocs.env.RUST_LOG"));
+    }
+
+    #[test]
+    fn layers_override_each_other() {
+        let user = layer(
+            "config.toml",
+            r#"
+[release]
+branch = "main"
+tag = "v{version}"
+"#,
+        );
+        let workspace = layer(
+            "Peniche.toml",
+            r#"
+[cmd]
+build = "cargo build"
+
+[release]
+branch = "develop"
+"#,
+        );
+        let environment = Layer::environment([
+            ("PENICHE_RELEASE__BRANCH".to_string(), "release".to_string()),
+            ("PENICHE_CONFIG".to_string(), "Other.toml".to_string()),
+            ("RUST_LOG".to_string(), "debug".to_string()),
+        ])
+        .unwrap();
+
+        let config =
+            Config::from_layers(&[user, workspace, environment], Path::new("/ws")).unwrap();
+        assert_eq!(config.release.branch.as_deref(), Some("release"));
+        assert_eq!(config.release.tag, "v{version}");
+        assert!(config.cmd.contains_key("build"));
+
+        assert_eq!(origin(&config, "release.branch"), Some(ENVIRONMENT_ORIGIN));
+        assert_eq!(origin(&config, "release.tag"), Some("config.toml"));
+        assert_eq!(origin(&config, "cmd.build"), Some("Peniche.toml"));
+        assert!(origin(&config, "config").is_none());
+    }
+
+    #[test]
+    fn environment_values_are_not_parsed() {
+        let environment = Layer::environment([
+            ("PENICHE_CMD__BUILD".to_string(), "42".to_string()),
+            (
+                "PENICHE_CMD__CHECK__COMMAND".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "PENICHE_CMD__CHECK__FOREACH".to_string(),
+                "true".to_string(),
+            ),
+            ("PENICHE_CMD__TEST__COMMAND".to_string(), "1".to_string()),
+            ("PENICHE_RELEASE__BRANCH".to_string(), "2024".to_string()),
+        ])
+        .unwrap();
+
+        let config = Config::from_layers(&[environment], Path::new("/ws")).unwrap();
+        assert_eq!(config.release.branch.as_deref(), Some("2024"));
+        assert_eq!(config.cmd["build"].platform_command().unwrap(), "42");
+        assert_eq!(config.cmd["check"].platform_command().unwrap(), "true");
+        assert_eq!(config.cmd["test"].platform_command().unwrap(), "1");
+        // `foreach` is the only boolean, parsed when it can be
+        assert!(config.cmd["check"].foreach());
+        assert!(!config.cmd["test"].foreach());
+
+        let environment = Layer::environment([
+            (
+                "PENICHE_CMD__TEST__COMMAND".to_string(),
+                "cargo test".to_string(),
+            ),
+            ("PENICHE_CMD__TEST__FOREACH".to_string(), "yes".to_string()),
+        ])
+        .unwrap();
+        let err = Config::from_layers(&[environment], Path::new("/ws")).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("'foreach' of command 'test' must be a boolean"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn member_commands_are_scoped() {
+        let mut config = Config::from_layers(
+            &[layer("Peniche.toml", "[cmd]\nbuild = \"cargo build\"\n")],
+            Path::new("/ws"),
+        )
+        .unwrap();
+        let member = layer(
+            "core/Peniche.toml",
+            r#"
+[cmd]
+runBench = { command = "cargo bench", depends_on = ["build"] }
+"#,
+        );
+        config
+            .add_member_commands("core", Path::new("/ws/core"), &member)
+            .unwrap();
+
+        let graph = config
+            .command_graph(&["core:runBench".to_string()])
+            .unwrap();
+        assert_eq!(names(&graph), ["build", "core:runBench"]);
+        assert_eq!(
+            origin(&config, "cmd.\"core:runBench\".command"),
+            Some("core/Peniche.toml")
+        );
     }
 }
@@ -0,0 +1,239 @@
+use anyhow::anyhow;
+use glob::Pattern;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use crate::git::affected_crates;
+use crate::graph::WorkspaceGraph;
+use crate::krate::Krate;
+use crate::workspace::Workspace;
+
+/// pnpm-style crate selector, shared by every command targeting workspace members.
+///
+/// - `core` or `svc-*`: crates matching a name or a glob
+/// - `core...` / `core^...`: with / only their transitive dependents
+/// - `...core` / `...^core`: with / only their transitive dependencies
+/// - `[origin/main]`: crates with files changed since a git reference
+/// - `!core`: removes the matching crates from the selection
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub exclude: bool,
+    pub selector: Selector,
+    pub dependencies: bool,
+    pub dependents: bool,
+    /// Whether the matched crates are selected, not only their dependencies/dependents
+    pub include_self: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Selector {
+    Name(Pattern),
+    Changed(String),
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s.trim();
+
+        let exclude = rest.starts_with('!');
+        rest = rest.trim_start_matches('!');
+
+        let mut include_self = true;
+
+        let dependencies = rest.starts_with("...");
+        if dependencies {
+            rest = &rest[3..];
+            if let Some(stripped) = rest.strip_prefix('^') {
+                include_self = false;
+                rest = stripped;
+            }
+        }
+
+        let dependents = rest.ends_with("...");
+        if dependents {
+            rest = &rest[..rest.len() - 3];
+            if let Some(stripped) = rest.strip_suffix('^') {
+                include_self = false;
+                rest = stripped;
+            }
+        }
+
+        let selector = if let Some(reference) = rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            if reference.is_empty() {
+                return Err(anyhow!("Missing git reference in filter '{}'", s));
+            }
+            Selector::Changed(reference.to_string())
+        } else if rest.is_empty() {
+            return Err(anyhow!("Missing crate name in filter '{}'", s));
+        } else {
+            Selector::Name(
+                Pattern::new(rest).map_err(|err| anyhow!("Invalid filter '{}': {}", s, err))?,
+            )
+        };
+
+        Ok(Filter {
+            exclude,
+            selector,
+            dependencies,
+            dependents,
+            include_self,
+        })
+    }
+}
+
+impl Filter {
+    /// Names of the workspace members matched by this filter, ignoring `exclude`.
+    pub fn resolve(&self, ws: &Workspace) -> anyhow::Result<BTreeSet<String>> {
+        let matched: BTreeSet<String> = match &self.selector {
+            Selector::Name(pattern) => ws
+                .crates
+                .keys()
+                .filter(|name| pattern.matches(name))
+                .cloned()
+                .collect(),
+            Selector::Changed(reference) => affected_crates(ws, reference)?.changed,
+        };
+
+        let mut selected = BTreeSet::new();
+        if self.include_self {
+            selected.extend(matched.iter().cloned());
+        }
+
+        if self.dependencies || self.dependents {
+            let graph = WorkspaceGraph::from_workspace(ws);
+            let roots = || matched.iter().map(String::as_str);
+
+            if self.dependencies {
+                selected.extend(graph.transitive_dependencies(roots()));
+            }
+            if self.dependents {
+                selected.extend(graph.transitive_dependents(roots()));
+            }
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Parses and applies `filters` to the workspace members, sorted by name. Crates matched by an
+/// inclusion filter are selected, then exclusions are removed. Only exclusions start from
+/// every member.
+pub fn select_crates<'a>(ws: &'a Workspace, filters: &[String]) -> anyhow::Result<Vec<&'a Krate>> {
+    let filters = filters
+        .iter()
+        .map(|filter| filter.parse())
+        .collect::<anyhow::Result<Vec<Filter>>>()?;
+
+    let mut selected = BTreeSet::new();
+    if filters.iter().all(|filter| filter.exclude) {
+        selected.extend(ws.crates.keys().cloned());
+    }
+
+    for filter in filters.iter().filter(|filter| !filter.exclude) {
+        selected.extend(filter.resolve(ws)?);
+    }
+
+    for filter in filters.iter().filter(|filter| filter.exclude) {
+        for name in filter.resolve(ws)? {
+            selected.remove(&name);
+        }
+    }
+
+    Ok(selected.iter().map(|name| &ws.crates[name]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::krate::KrateSource;
+    use std::path::PathBuf;
+
+    fn krate(name: &str) -> Krate {
+        Krate::new(
+            name.to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(PathBuf::from("/ws/crates").join(name)),
+        )
+    }
+
+    /// `cli` -> `api` -> `core`, `svc-auth` -> `core`, and a standalone `docs`.
+    fn workspace() -> Workspace {
+        let mut ws = Workspace::new(PathBuf::from("/ws"));
+        for (name, deps) in [
+            ("core", vec![]),
+            ("api", vec!["core"]),
+            ("cli", vec!["api"]),
+            ("svc-auth", vec!["core"]),
+            ("docs", vec![]),
+        ] {
+            let mut member = krate(name);
+            for dep in deps {
+                member.dependencies.insert(dep.to_string(), krate(dep));
+            }
+            ws.crates.insert(name.to_string(), member);
+        }
+        ws
+    }
+
+    fn select(filters: &[&str]) -> Vec<String> {
+        let ws = workspace();
+        let filters: Vec<String> = filters.iter().map(|f| f.to_string()).collect();
+        select_crates(&ws, &filters)
+            .unwrap()
+            .into_iter()
+            .map(|krate| krate.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parse_filters() {
+        let filter: Filter = "!core^...".parse().unwrap();
+        assert!(filter.exclude);
+        assert!(filter.dependents && !filter.dependencies);
+        assert!(!filter.include_self);
+        assert!(matches!(filter.selector, Selector::Name(ref p) if p.as_str() == "core"));
+
+        let filter: Filter = "...svc-*".parse().unwrap();
+        assert!(!filter.exclude);
+        assert!(filter.dependencies && !filter.dependents);
+        assert!(filter.include_self);
+
+        let filter: Filter = "[origin/main]...".parse().unwrap();
+        assert!(matches!(filter.selector, Selector::Changed(ref r) if r == "origin/main"));
+        assert!(filter.dependents);
+    }
+
+    #[test]
+    fn parse_invalid_filters() {
+        for filter in ["", "!", "...", "[]", "[a"] {
+            assert!(filter.parse::<Filter>().is_err(), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn select_by_name_and_glob() {
+        assert_eq!(select(&["core"]), ["core"]);
+        assert_eq!(select(&["svc-*", "docs"]), ["docs", "svc-auth"]);
+        assert!(select(&["unknown"]).is_empty());
+    }
+
+    #[test]
+    fn select_dependents_and_dependencies() {
+        assert_eq!(select(&["core..."]), ["api", "cli", "core", "svc-auth"]);
+        assert_eq!(select(&["core^..."]), ["api", "cli", "svc-auth"]);
+        assert_eq!(select(&["...cli"]), ["api", "cli", "core"]);
+        assert_eq!(select(&["...^cli"]), ["api", "core"]);
+    }
+
+    #[test]
+    fn select_exclusions() {
+        assert_eq!(select(&["!docs"]), ["api", "cli", "core", "svc-auth"]);
+        assert_eq!(select(&["core...", "!cli"]), ["api", "core", "svc-auth"]);
+        assert_eq!(select(&["!core...", "!docs"]), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,439 @@
+use anyhow::{anyhow, Context as _};
+use git2::{DiffOptions, Oid, Repository, StatusOptions};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::graph::WorkspaceGraph;
+use crate::workspace::Workspace;
+
+/// Files at the workspace root whose changes affect every member.
+const GLOBAL_FILES: &[&str] = &[
+    "Cargo.toml",
+    "Cargo.lock",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    ".cargo/config.toml",
+];
+
+/// Members affected by the changes since a git reference.
+#[derive(Debug, Default, Serialize)]
+pub struct Affected {
+    /// Members owning a changed file
+    pub changed: BTreeSet<String>,
+    /// Members depending on a changed member, directly or not
+    pub dependents: BTreeSet<String>,
+}
+
+impl Affected {
+    pub fn all(&self) -> BTreeSet<String> {
+        self.changed.union(&self.dependents).cloned().collect()
+    }
+}
+
+/// Opens the git repository containing `path`.
+pub fn open_repository(path: &Path) -> anyhow::Result<Repository> {
+    Repository::discover(path)
+        .with_context(|| format!("Failed to find a git repository from {:?}", path))
+}
+
+/// Name of the checked out branch, `None` for a detached or unborn HEAD.
+pub fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(String::from)
+}
+
+/// Fails when tracked files have staged or unstaged changes.
+pub fn ensure_clean(repo: &Repository) -> anyhow::Result<()> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let dirty: Vec<String> = statuses
+        .iter()
+        .filter_map(|entry| entry.path().map(String::from))
+        .collect();
+
+    if dirty.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The working tree has uncommitted changes:\n  {}",
+            dirty.join("\n  ")
+        ))
+    }
+}
+
+/// Stages `paths`, removing the deleted ones from the index, and commits them on top of `HEAD`.
+pub fn commit_paths(repo: &Repository, paths: &[PathBuf], message: &str) -> anyhow::Result<Oid> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("Cannot commit in a bare repository"))?;
+    let workdir = workdir.canonicalize().unwrap_or(workdir.to_path_buf());
+
+    let mut index = repo.index()?;
+    for path in paths {
+        // Deleted files cannot be canonicalized, their parent directory still can
+        let exists = path.exists();
+        let path = match (exists, path.parent(), path.file_name()) {
+            (false, Some(parent), Some(name)) => parent
+                .canonicalize()
+                .map(|parent| parent.join(name))
+                .unwrap_or(path.clone()),
+            _ => path.canonicalize().unwrap_or(path.clone()),
+        };
+        let relative = path
+            .strip_prefix(&workdir)
+            .with_context(|| format!("{:?} is outside of the git repository", path))?;
+        if exists {
+            index.add_path(relative)?;
+        } else {
+            index.remove_path(relative)?;
+        }
+    }
+    index.write()?;
+
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = repo
+        .signature()
+        .context("Failed to find the git user name and email")?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?)
+}
+
+/// Creates an annotated tag on `target`, failing if it already exists.
+pub fn create_tag(repo: &Repository, name: &str, target: Oid, message: &str) -> anyhow::Result<()> {
+    let target = repo.find_object(target, None)?;
+    let signature = repo
+        .signature()
+        .context("Failed to find the git user name and email")?;
+
+    repo.tag(name, &target, &signature, message, false)
+        .with_context(|| format!("Failed to create tag '{}'", name))?;
+    Ok(())
+}
+
+/// Lists the files changed since `base`, including staged, unstaged and untracked changes.
+///
+/// Changes are compared against the merge base of `base` and `HEAD`, so commits landing on
+/// `base` after the current branch forked are not reported. Paths are absolute.
+pub fn changed_files(path: &Path, base: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let repo = open_repository(path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("Cannot diff a bare repository"))?
+        .to_path_buf();
+
+    let base_commit = repo
+        .revparse_single(base)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Failed to resolve git reference '{}'", base))?;
+
+    let tree = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(head) => {
+            let merge_base = repo
+                .merge_base(base_commit.id(), head.id())
+                .with_context(|| format!("No common ancestor between HEAD and '{}'", base))?;
+            repo.find_commit(merge_base)?.tree()?
+        }
+        // Unborn branch: everything is compared against the base itself
+        Err(_) => base_commit.tree()?,
+    };
+
+    let mut opts = DiffOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(true);
+
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut opts))?;
+
+    let mut files: Vec<PathBuf> = diff
+        .deltas()
+        .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(|path| workdir.join(path))
+        .collect();
+    files.sort();
+    files.dedup();
+
+    Ok(files)
+}
+
+/// Maps the files changed since `base` (uncommitted changes included) to the members owning
+/// them, then expands to all of their transitive dependents. A change to a workspace-wide
+/// file such as `Cargo.lock` affects every member.
+pub fn affected_crates(ws: &Workspace, base: &str) -> anyhow::Result<Affected> {
+    let mut affected = Affected::default();
+
+    for file in changed_files(&ws.path, base)? {
+        // Checked first: the workspace root may be a package owning these files too
+        if GLOBAL_FILES
+            .iter()
+            .any(|global| ws.path.join(global) == file)
+        {
+            affected.changed.extend(ws.crates.keys().cloned());
+        } else if let Some(krate) = ws.owning_krate(&file) {
+            affected.changed.insert(krate.name.clone());
+        }
+    }
+
+    let graph = WorkspaceGraph::from_workspace(ws);
+    affected.dependents = graph
+        .transitive_dependents(affected.changed.iter().map(String::as_str))
+        .difference(&affected.changed)
+        .cloned()
+        .collect();
+
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::krate::{Krate, KrateSource};
+    use git2::RepositoryInitOptions;
+    use tempfile::TempDir;
+
+    fn init_repo() -> (TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = RepositoryInitOptions::new();
+        opts.initial_head("main");
+        let repo = Repository::init_opts(dir.path(), &opts).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Peniche").unwrap();
+        config.set_str("user.email", "peniche@example.com").unwrap();
+
+        (dir, repo)
+    }
+
+    fn write(dir: &TempDir, name: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn names(set: &BTreeSet<String>) -> Vec<&str> {
+        set.iter().map(String::as_str).collect()
+    }
+
+    /// A root package with `app` -> `core` and `docs` members under `crates/`.
+    fn workspace(dir: &TempDir) -> Workspace {
+        let mut ws = Workspace::new(dir.path().to_path_buf());
+        for (name, path) in [
+            ("root", dir.path().to_path_buf()),
+            ("core", dir.path().join("crates/core")),
+            ("app", dir.path().join("crates/app")),
+            ("docs", dir.path().join("crates/docs")),
+        ] {
+            let krate = Krate::new(
+                name.to_string(),
+                "0.1.0".to_string(),
+                KrateSource::Path(path),
+            );
+            ws.crates.insert(name.to_string(), krate);
+        }
+
+        let core = Krate::new(
+            "core".to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(dir.path().join("crates/core")),
+        );
+        let app = ws.crates.get_mut("app").unwrap();
+        app.dependencies.insert("core".to_string(), core);
+        ws
+    }
+
+    fn head_tree_contains(repo: &Repository, name: &str) -> bool {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        tree.get_path(Path::new(name)).is_ok()
+    }
+
+    #[test]
+    fn current_branch_follows_head() {
+        let (dir, repo) = init_repo();
+        assert_eq!(current_branch(&repo), None, "unborn branch");
+
+        let file = write(&dir, "README.md", "hello");
+        let oid = commit_paths(&repo, &[file], "initial commit").unwrap();
+        assert_eq!(current_branch(&repo).as_deref(), Some("main"));
+
+        repo.set_head_detached(oid).unwrap();
+        assert_eq!(current_branch(&repo), None, "detached HEAD");
+    }
+
+    #[test]
+    fn ensure_clean_ignores_untracked_files() {
+        let (dir, repo) = init_repo();
+        let file = write(&dir, "README.md", "hello");
+        commit_paths(&repo, &[file], "initial commit").unwrap();
+        ensure_clean(&repo).unwrap();
+
+        write(&dir, "notes.txt", "untracked");
+        ensure_clean(&repo).unwrap();
+
+        write(&dir, "README.md", "changed");
+        let err = ensure_clean(&repo).unwrap_err();
+        assert!(err.to_string().contains("README.md"), "{}", err);
+    }
+
+    #[test]
+    fn commit_paths_stages_added_and_deleted_files() {
+        let (dir, repo) = init_repo();
+        let kept = write(&dir, "crates/core/Cargo.toml", "[package]");
+        let deleted = write(&dir, "crates/core/CHANGELOG.md", "# Changelog");
+        let first = commit_paths(&repo, &[kept.clone(), deleted.clone()], "first").unwrap();
+        assert!(head_tree_contains(&repo, "crates/core/Cargo.toml"));
+        assert!(head_tree_contains(&repo, "crates/core/CHANGELOG.md"));
+
+        std::fs::remove_file(&deleted).unwrap();
+        write(&dir, "crates/core/Cargo.toml", "[package]\nname = \"core\"");
+        let second = commit_paths(&repo, &[kept, deleted], "second").unwrap();
+
+        let commit = repo.find_commit(second).unwrap();
+        assert_eq!(commit.message(), Some("second"));
+        assert_eq!(commit.parent_id(0).unwrap(), first);
+        assert!(head_tree_contains(&repo, "crates/core/Cargo.toml"));
+        assert!(!head_tree_contains(&repo, "crates/core/CHANGELOG.md"));
+        ensure_clean(&repo).unwrap();
+    }
+
+    #[test]
+    fn commit_paths_rejects_files_outside_of_the_repository() {
+        let (_dir, repo) = init_repo();
+        let outside = tempfile::tempdir().unwrap();
+        let file = outside.path().join("Cargo.toml");
+        std::fs::write(&file, "[package]").unwrap();
+
+        assert!(commit_paths(&repo, &[file], "outside").is_err());
+    }
+
+    #[test]
+    fn create_tag_is_annotated_and_not_overwritten() {
+        let (dir, repo) = init_repo();
+        let file = write(&dir, "README.md", "hello");
+        let oid = commit_paths(&repo, &[file], "initial commit").unwrap();
+
+        create_tag(&repo, "core-v0.1.0", oid, "core 0.1.0").unwrap();
+        let tag = repo
+            .revparse_single("refs/tags/core-v0.1.0")
+            .unwrap()
+            .into_tag()
+            .expect("annotated tag");
+        assert_eq!(tag.message(), Some("core 0.1.0"));
+        assert_eq!(tag.target_id(), oid);
+
+        assert!(create_tag(&repo, "core-v0.1.0", oid, "again").is_err());
+    }
+
+    #[test]
+    fn changed_files_include_uncommitted_changes() {
+        let (dir, repo) = init_repo();
+        let readme = write(&dir, "README.md", "hello");
+        let lib = write(&dir, "crates/core/src/lib.rs", "");
+        let base = commit_paths(&repo, &[readme.clone(), lib.clone()], "initial commit").unwrap();
+
+        // Work landing upstream after the fork is not a change of the branch
+        let upstream_tree = {
+            let base_tree = repo.find_commit(base).unwrap().tree().unwrap();
+            let mut builder = repo.treebuilder(Some(&base_tree)).unwrap();
+            let blob = repo.blob(b"upstream").unwrap();
+            builder.insert("upstream.txt", blob, 0o100644).unwrap();
+            repo.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let signature = repo.signature().unwrap();
+        let base_commit = repo.find_commit(base).unwrap();
+        repo.commit(
+            Some("refs/heads/upstream"),
+            &signature,
+            &signature,
+            "upstream work",
+            &upstream_tree,
+            &[&base_commit],
+        )
+        .unwrap();
+
+        assert!(changed_files(dir.path(), "upstream").unwrap().is_empty());
+
+        write(&dir, "crates/core/src/lib.rs", "// committed");
+        commit_paths(&repo, &[lib.clone()], "fix: core").unwrap();
+        let staged = write(&dir, "crates/app/src/main.rs", "fn main() {}");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("crates/app/src/main.rs")).unwrap();
+        index.write().unwrap();
+        write(&dir, "README.md", "unstaged");
+        let untracked = write(&dir, "notes/todo.txt", "untracked");
+
+        let mut expected = vec![readme, lib, staged, untracked];
+        expected.sort();
+        assert_eq!(changed_files(dir.path(), "upstream").unwrap(), expected);
+
+        assert!(changed_files(dir.path(), "unknown").is_err());
+    }
+
+    #[test]
+    fn affected_crates_expand_to_dependents() {
+        let (dir, repo) = init_repo();
+        let files = [
+            write(&dir, "Cargo.toml", "[workspace]"),
+            write(&dir, "src/main.rs", ""),
+            write(&dir, "crates/core/src/lib.rs", ""),
+            write(&dir, "crates/app/src/main.rs", ""),
+            write(&dir, "crates/docs/README.md", ""),
+        ];
+        let base = commit_paths(&repo, &files, "initial commit")
+            .unwrap()
+            .to_string();
+        let ws = workspace(&dir);
+
+        assert!(affected_crates(&ws, &base).unwrap().all().is_empty());
+
+        write(&dir, "crates/core/src/lib.rs", "// changed");
+        let affected = affected_crates(&ws, &base).unwrap();
+        assert_eq!(names(&affected.changed), ["core"]);
+        assert_eq!(names(&affected.dependents), ["app"]);
+
+        // The innermost member owns a file, the root package its own sources
+        write(&dir, "src/main.rs", "fn main() {}");
+        let affected = affected_crates(&ws, &base).unwrap();
+        assert_eq!(names(&affected.changed), ["core", "root"]);
+        assert_eq!(names(&affected.all()), ["app", "core", "root"]);
+    }
+
+    #[test]
+    fn workspace_files_affect_every_member() {
+        let (dir, repo) = init_repo();
+        let manifest = write(&dir, "Cargo.toml", "[workspace]");
+        let base = commit_paths(&repo, &[manifest], "initial commit")
+            .unwrap()
+            .to_string();
+        let ws = workspace(&dir);
+
+        // The root package does not take the workspace manifest for itself
+        write(&dir, "Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]");
+        let affected = affected_crates(&ws, &base).unwrap();
+        assert_eq!(names(&affected.changed), ["app", "core", "docs", "root"]);
+        assert!(affected.dependents.is_empty());
+
+        write(&dir, "Cargo.toml", "[workspace]");
+        write(&dir, ".cargo/config.toml", "[build]");
+        let affected = affected_crates(&ws, &base).unwrap();
+        assert_eq!(affected.changed.len(), 4);
+
+        std::fs::remove_dir_all(dir.path().join(".cargo")).unwrap();
+        write(&dir, "crates/docs/Cargo.lock", "");
+        let affected = affected_crates(&ws, &base).unwrap();
+        assert_eq!(names(&affected.changed), ["docs"]);
+    }
+}
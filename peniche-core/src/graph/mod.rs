@@ -0,0 +1,516 @@
+use anyhow::anyhow;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef as _;
+use petgraph::Direction;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Write as _;
+
+use crate::krate::DependencyKind;
+use crate::workspace::Workspace;
+
+/// Dependency graph between the members of a workspace.
+///
+/// Nodes are member names and an edge `a -> b` means `a` depends on `b`, weighted by the
+/// strongest table (`dependencies`, `build-dependencies`, `dev-dependencies`) declaring it.
+#[derive(Debug, Default)]
+pub struct WorkspaceGraph {
+    pub graph: DiGraph<String, DependencyKind>,
+    nodes: HashMap<String, NodeIndex>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonGraph<'a> {
+    nodes: Vec<&'a str>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonEdge<'a> {
+    from: &'a str,
+    to: &'a str,
+    kind: DependencyKind,
+}
+
+impl WorkspaceGraph {
+    pub fn from_workspace(ws: &Workspace) -> Self {
+        let mut graph = Self::default();
+
+        let mut names: Vec<&String> = ws.crates.keys().collect();
+        names.sort();
+        for name in names {
+            let idx = graph.graph.add_node(name.clone());
+            graph.nodes.insert(name.clone(), idx);
+        }
+
+        for krate in ws.crates.values() {
+            for dep in krate.dependencies.values() {
+                let is_local = dep.path.is_local();
+                if let (true, Some(&to)) = (is_local, graph.nodes.get(&dep.name)) {
+                    let from = graph.nodes[&krate.name];
+                    graph.graph.add_edge(from, to, dep.dep_kind);
+                }
+            }
+        }
+
+        graph
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.nodes.contains_key(name)
+    }
+
+    /// Members `name` depends on directly, with the kind of each link.
+    pub fn direct_dependencies(&self, name: &str) -> Vec<(&str, DependencyKind)> {
+        self.neighbors(name, Direction::Outgoing)
+    }
+
+    /// Members depending directly on `name`, with the kind of each link.
+    pub fn direct_dependents(&self, name: &str) -> Vec<(&str, DependencyKind)> {
+        self.neighbors(name, Direction::Incoming)
+    }
+
+    /// Members the `roots` depend on, directly or not. A root is only part of the result when
+    /// another root depends on it.
+    pub fn transitive_dependencies<'a>(
+        &self,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeSet<String> {
+        self.reachable(roots, Direction::Outgoing)
+    }
+
+    /// Members depending on the `roots`, directly or not. A root is only part of the result
+    /// when it depends on another root.
+    pub fn transitive_dependents<'a>(
+        &self,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeSet<String> {
+        self.reachable(roots, Direction::Incoming)
+    }
+
+    /// Dependency cycles, each listed as `a -> b -> ... -> a`. Cycles going through a
+    /// dev-dependency are only reported when `include_dev` is set, cargo accepts them.
+    pub fn cycles(&self, include_dev: bool) -> Vec<Vec<String>> {
+        let graph = self.without_dev(include_dev);
+
+        tarjan_scc(&graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || graph.contains_edge(scc[0], scc[0]))
+            .map(|scc| cycle_path(&graph, &scc, Direction::Outgoing))
+            .collect()
+    }
+
+    /// Members sorted so that each one comes after all of its dependencies, ties broken by name.
+    /// Dev-dependencies are ignored, cycles are reported as an error.
+    pub fn topological_order(&self) -> anyhow::Result<Vec<String>> {
+        let graph = self.without_dev(false);
+
+        let mut remaining: HashMap<NodeIndex, usize> = graph
+            .node_indices()
+            .map(|idx| {
+                let mut deps: Vec<NodeIndex> = graph.neighbors(idx).collect();
+                deps.sort();
+                deps.dedup();
+                (idx, deps.len())
+            })
+            .collect();
+
+        let mut ready: BTreeSet<&String> = remaining
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&idx, _)| &graph[idx])
+            .collect();
+
+        let mut order = vec![];
+        while let Some(name) = ready.pop_first() {
+            order.push(name.clone());
+
+            let idx = self.nodes[name];
+            let mut dependents: Vec<NodeIndex> =
+                graph.neighbors_directed(idx, Direction::Incoming).collect();
+            dependents.sort();
+            dependents.dedup();
+
+            for dependent in dependents {
+                let count = remaining.get_mut(&dependent).expect("node of the graph");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(&graph[dependent]);
+                }
+            }
+        }
+
+        if order.len() != graph.node_count() {
+            let cycles: Vec<String> = self
+                .cycles(false)
+                .iter()
+                .map(|cycle| cycle.join(" -> "))
+                .collect();
+            return Err(anyhow!(
+                "Dependency cycle detected between workspace members:\n  {}",
+                cycles.join("\n  ")
+            ));
+        }
+
+        Ok(order)
+    }
+
+    /// Shortest chain of dependencies leading from `from` to `to`, both included, ignoring
+    /// dev-dependencies.
+    pub fn dependency_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let (&start, &goal) = (self.nodes.get(from)?, self.nodes.get(to)?);
+        let graph = self.without_dev(false);
+
+        let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(idx) = queue.pop_front() {
+            if idx == goal {
+                let mut path = vec![graph[goal].clone()];
+                let mut current = goal;
+                while let Some(&prev) = previous.get(&current) {
+                    path.push(graph[prev].clone());
+                    current = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for next in graph.neighbors(idx) {
+                if next != start && !previous.contains_key(&next) {
+                    previous.insert(next, idx);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Graphviz representation, dev and build dependencies drawn with dashed edges.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph workspace {\n");
+        for idx in self.graph.node_indices() {
+            let _ = writeln!(dot, "    \"{}\";", self.graph[idx]);
+        }
+        for (from, to, kind) in self.edges() {
+            let _ = match kind {
+                DependencyKind::Normal => writeln!(dot, "    \"{}\" -> \"{}\";", from, to),
+                _ => writeln!(
+                    dot,
+                    "    \"{}\" -> \"{}\" [style=dashed, label=\"{}\"];",
+                    from,
+                    to,
+                    kind_label(kind)
+                ),
+            };
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Mermaid flowchart, dev and build dependencies drawn with dotted labeled edges.
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("graph TD\n");
+        for idx in self.graph.node_indices() {
+            let _ = writeln!(mermaid, "    n{}[\"{}\"]", idx.index(), self.graph[idx]);
+        }
+        for edge in self.graph.raw_edges() {
+            let (from, to) = (edge.source().index(), edge.target().index());
+            let _ = match edge.weight {
+                DependencyKind::Normal => writeln!(mermaid, "    n{} --> n{}", from, to),
+                kind => writeln!(mermaid, "    n{} -.->|{}| n{}", from, kind_label(kind), to),
+            };
+        }
+        mermaid
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let json = JsonGraph {
+            nodes: self.graph.node_weights().map(String::as_str).collect(),
+            edges: self
+                .edges()
+                .map(|(from, to, kind)| JsonEdge { from, to, kind })
+                .collect(),
+        };
+
+        Ok(serde_json::to_string_pretty(&json)?)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (&str, &str, DependencyKind)> {
+        self.graph.raw_edges().iter().map(|edge| {
+            (
+                self.graph[edge.source()].as_str(),
+                self.graph[edge.target()].as_str(),
+                edge.weight,
+            )
+        })
+    }
+
+    fn neighbors(&self, name: &str, direction: Direction) -> Vec<(&str, DependencyKind)> {
+        let Some(&idx) = self.nodes.get(name) else {
+            return vec![];
+        };
+
+        let mut neighbors: Vec<(&str, DependencyKind)> = self
+            .graph
+            .edges_directed(idx, direction)
+            .map(|edge| {
+                let other = match direction {
+                    Direction::Outgoing => edge.target(),
+                    Direction::Incoming => edge.source(),
+                };
+                (self.graph[other].as_str(), *edge.weight())
+            })
+            .collect();
+        neighbors.sort();
+        neighbors
+    }
+
+    fn reachable<'a>(
+        &self,
+        roots: impl IntoIterator<Item = &'a str>,
+        direction: Direction,
+    ) -> BTreeSet<String> {
+        let mut visited = BTreeSet::new();
+        let mut stack: Vec<NodeIndex> = roots
+            .into_iter()
+            .filter_map(|name| self.nodes.get(name).copied())
+            .collect();
+
+        while let Some(idx) = stack.pop() {
+            for next in self.graph.neighbors_directed(idx, direction) {
+                if visited.insert(self.graph[next].clone()) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        visited
+    }
+
+    /// Copy of the graph keeping every node at the same index, optionally without dev edges.
+    fn without_dev(&self, include_dev: bool) -> DiGraph<String, DependencyKind> {
+        self.graph.filter_map(
+            |_, name| Some(name.clone()),
+            |_, &kind| (include_dev || kind != DependencyKind::Dev).then_some(kind),
+        )
+    }
+}
+
+fn kind_label(kind: DependencyKind) -> &'static str {
+    match kind {
+        DependencyKind::Normal => "normal",
+        DependencyKind::Build => "build",
+        DependencyKind::Dev => "dev",
+    }
+}
+
+/// Walks a strongly connected component following `direction` until a node repeats,
+/// returning the names along that cycle.
+pub(crate) fn cycle_path<E>(
+    graph: &DiGraph<String, E>,
+    scc: &[NodeIndex],
+    direction: Direction,
+) -> Vec<String> {
+    let mut path = vec![scc[0]];
+
+    loop {
+        let current = path[path.len() - 1];
+        let next = graph
+            .neighbors_directed(current, direction)
+            .find(|idx| scc.contains(idx))
+            .unwrap_or(scc[0]);
+
+        if let Some(start) = path.iter().position(|&idx| idx == next) {
+            return path[start..]
+                .iter()
+                .chain(std::iter::once(&next))
+                .map(|&idx| graph[idx].clone())
+                .collect();
+        }
+
+        path.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::krate::{Krate, KrateSource};
+    use std::path::PathBuf;
+
+    fn krate(name: &str) -> Krate {
+        Krate::new(
+            name.to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(PathBuf::from("/ws").join(name)),
+        )
+    }
+
+    fn graph(names: &[&str], links: &[(&str, &str, DependencyKind)]) -> WorkspaceGraph {
+        let mut ws = Workspace::new(PathBuf::from("/ws"));
+        for name in names {
+            ws.crates.insert(name.to_string(), krate(name));
+        }
+        for (from, to, kind) in links {
+            let mut dep = krate(to);
+            dep.dep_kind = *kind;
+            let from = ws.crates.get_mut(*from).unwrap();
+            from.dependencies.insert(to.to_string(), dep);
+        }
+        // Registry dependencies are not part of the graph
+        let serde = Krate::new("serde".to_string(), "1".to_string(), KrateSource::Registry);
+        let api = ws.crates.get_mut("api").unwrap();
+        api.dependencies.insert("serde".to_string(), serde);
+
+        WorkspaceGraph::from_workspace(&ws)
+    }
+
+    /// `app` -> `api` -> `core`, `app` -build-> `macros`, `core` -dev-> `testkit` -> `core`,
+    /// and a standalone `docs`.
+    fn workspace_graph() -> WorkspaceGraph {
+        graph(
+            &["api", "app", "core", "docs", "macros", "testkit"],
+            &[
+                ("app", "api", DependencyKind::Normal),
+                ("app", "macros", DependencyKind::Build),
+                ("api", "core", DependencyKind::Normal),
+                ("core", "testkit", DependencyKind::Dev),
+                ("testkit", "core", DependencyKind::Normal),
+            ],
+        )
+    }
+
+    #[test]
+    fn direct_links() {
+        let graph = workspace_graph();
+        assert!(graph.contains("docs"));
+        assert!(!graph.contains("serde"));
+        assert_eq!(graph.graph.edge_count(), 5);
+
+        assert_eq!(
+            graph.direct_dependencies("app"),
+            [
+                ("api", DependencyKind::Normal),
+                ("macros", DependencyKind::Build)
+            ]
+        );
+        assert_eq!(
+            graph.direct_dependents("core"),
+            [
+                ("api", DependencyKind::Normal),
+                ("testkit", DependencyKind::Normal)
+            ]
+        );
+        assert!(graph.direct_dependencies("unknown").is_empty());
+    }
+
+    #[test]
+    fn transitive_links() {
+        let graph = workspace_graph();
+        let names = |set: BTreeSet<String>| set.into_iter().collect::<Vec<_>>();
+
+        // `core` reaches itself through its dev-dependency on `testkit`
+        assert_eq!(
+            names(graph.transitive_dependents(["core"])),
+            ["api", "app", "core", "testkit"]
+        );
+        assert_eq!(
+            names(graph.transitive_dependencies(["app"])),
+            ["api", "core", "macros", "testkit"]
+        );
+        assert_eq!(
+            names(graph.transitive_dependencies(["api", "app"])).len(),
+            4
+        );
+        assert!(graph.transitive_dependents(["app", "docs"]).is_empty());
+    }
+
+    #[test]
+    fn topological_order_ignores_dev_dependencies() {
+        let order = workspace_graph().topological_order().unwrap();
+        assert_eq!(order, ["core", "api", "docs", "macros", "app", "testkit"]);
+    }
+
+    #[test]
+    fn cycles_through_dev_dependencies_are_optional() {
+        let graph = workspace_graph();
+        assert!(graph.cycles(false).is_empty());
+
+        let cycles = graph.cycles(true);
+        assert_eq!(cycles.len(), 1);
+        let cycle = &cycles[0];
+        assert_eq!(cycle.len(), 3);
+        assert_eq!(cycle.first(), cycle.last());
+        assert!(cycle.contains(&"core".to_string()));
+        assert!(cycle.contains(&"testkit".to_string()));
+    }
+
+    #[test]
+    fn topological_order_reports_cycles() {
+        let graph = graph(
+            &["api", "core", "base"],
+            &[
+                ("api", "core", DependencyKind::Normal),
+                ("core", "base", DependencyKind::Build),
+                ("base", "core", DependencyKind::Normal),
+            ],
+        );
+
+        let err = graph.topological_order().unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+        assert!(
+            err.contains("core -> base -> core") || err.contains("base -> core -> base"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn dependency_path_is_the_shortest_chain() {
+        let graph = workspace_graph();
+        assert_eq!(
+            graph.dependency_path("app", "core").unwrap(),
+            ["app", "api", "core"]
+        );
+        assert_eq!(
+            graph.dependency_path("testkit", "core").unwrap(),
+            ["testkit", "core"]
+        );
+        // Dev-dependencies do not count, nor do reversed links
+        assert!(graph.dependency_path("core", "testkit").is_none());
+        assert!(graph.dependency_path("core", "app").is_none());
+        assert!(graph.dependency_path("app", "unknown").is_none());
+    }
+
+    #[test]
+    fn exports() {
+        let graph = workspace_graph();
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph workspace {\n"));
+        assert!(dot.contains("    \"docs\";\n"));
+        assert!(dot.contains("    \"app\" -> \"api\";\n"));
+        assert!(dot.contains("    \"app\" -> \"macros\" [style=dashed, label=\"build\"];\n"));
+
+        // Nodes are indexed by name
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("graph TD\n    n0[\"api\"]\n"));
+        assert!(mermaid.contains("    n1 --> n0\n"));
+        assert!(mermaid.contains("    n2 -.->|dev| n5\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["nodes"],
+            serde_json::json!(["api", "app", "core", "docs", "macros", "testkit"])
+        );
+        let edges = json["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 5);
+        assert!(edges.contains(&serde_json::json!({
+            "from": "app",
+            "to": "macros",
+            "kind": "build",
+        })));
+    }
+}
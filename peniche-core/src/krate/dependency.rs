@@ -0,0 +1,157 @@
+use anyhow::anyhow;
+use semver::VersionReq;
+
+use crate::krate::DependencyKind;
+
+/// Where and how a dependency is declared in a member manifest.
+#[derive(Debug, Default, Clone)]
+pub struct DependencyOptions {
+    pub kind: DependencyKind,
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    pub optional: bool,
+    /// Name the dependency is imported under, `package` pointing to the real crate
+    pub rename: Option<String>,
+    /// `cfg(..)` expression or target triple of a `[target.'..'.dependencies]` table
+    pub target: Option<String>,
+    /// Declare the requirement in `[workspace.dependencies]` and inherit it in the member
+    pub workspace: bool,
+}
+
+impl DependencyOptions {
+    /// Path of the table the dependency goes in, such as `["target", "cfg(unix)", "dependencies"]`.
+    pub fn table_path(&self) -> Vec<String> {
+        let table = match self.kind {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Build => "build-dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+        };
+
+        match &self.target {
+            Some(target) => vec!["target".to_string(), target.clone(), table.to_string()],
+            None => vec![table.to_string()],
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.optional && self.kind == DependencyKind::Dev {
+            return Err(anyhow!("Dev-dependencies cannot be optional"));
+        }
+        if let Some(rename) = &self.rename {
+            if rename.is_empty()
+                || !rename
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(anyhow!("Invalid dependency name '{}'", rename));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a `name@requirement` specification, the requirement being optional.
+pub fn parse_dependency_spec(spec: &str) -> anyhow::Result<(String, Option<String>)> {
+    let (name, requirement) = match spec.split_once('@') {
+        Some((name, requirement)) => (name, Some(requirement)),
+        None => (spec, None),
+    };
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!("Invalid crate name '{}'", name));
+    }
+
+    if let Some(requirement) = requirement {
+        VersionReq::parse(requirement).map_err(|err| {
+            anyhow!(
+                "Invalid requirement '{}' for {}: {}",
+                requirement,
+                name,
+                err
+            )
+        })?;
+    }
+
+    Ok((name.to_string(), requirement.map(String::from)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(
+            parse_dependency_spec("serde").unwrap(),
+            ("serde".to_string(), None)
+        );
+        assert_eq!(
+            parse_dependency_spec("serde_json@1.0").unwrap(),
+            ("serde_json".to_string(), Some("1.0".to_string()))
+        );
+        assert_eq!(
+            parse_dependency_spec("tokio-util@>=0.7, <0.8").unwrap(),
+            ("tokio-util".to_string(), Some(">=0.7, <0.8".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_specs() {
+        for spec in ["", "@1", "ser de", "serde/derive@1"] {
+            let err = parse_dependency_spec(spec).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid crate name"), "{}", err);
+        }
+
+        let err = parse_dependency_spec("serde@one").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Invalid requirement 'one' for serde"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn table_paths() {
+        let options = |kind, target: Option<&str>| DependencyOptions {
+            kind,
+            target: target.map(String::from),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options(DependencyKind::Normal, None).table_path(),
+            ["dependencies"]
+        );
+        assert_eq!(
+            options(DependencyKind::Build, None).table_path(),
+            ["build-dependencies"]
+        );
+        assert_eq!(
+            options(DependencyKind::Dev, Some("cfg(unix)")).table_path(),
+            ["target", "cfg(unix)", "dev-dependencies"]
+        );
+    }
+
+    #[test]
+    fn validate_options() {
+        let options = DependencyOptions {
+            kind: DependencyKind::Dev,
+            optional: true,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        for (rename, valid) in [("serde-alias", true), ("", false), ("my alias", false)] {
+            let options = DependencyOptions {
+                rename: Some(rename.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(options.validate().is_ok(), valid, "{}", rename);
+        }
+    }
+}
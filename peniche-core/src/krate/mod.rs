@@ -1,7 +1,8 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, Context as _};
 use cargo::{
     core::{
         compiler::{CompileMode, MessageFormat},
+        dependency::DepKind,
         Dependency, EitherManifest, SourceId,
     },
     ops::{self, CompileOptions, NewOptions},
@@ -14,19 +15,115 @@ use cargo::{
     },
     GlobalContext,
 };
+use cargo_util::paths::write_atomic;
 use semver::VersionReq;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf, str::FromStr, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    vec,
+};
+use toml_edit::DocumentMut;
 
+use crate::plugin::hooks::{HookPoint, Hooks, CRATE_ENV, CRATE_PATH_ENV};
+use crate::release::manifest::{dependency_name, package_dependency_tables_mut};
 use crate::resolve_manifest_path;
 
+pub mod dependency;
+
+use dependency::DependencyOptions;
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub enum KrateSource {
+    /// crates.io
     #[default]
     Registry,
+    /// Alternative registry, by its name in `.cargo/config.toml` or its index URL
+    AlternativeRegistry(String),
     Path(PathBuf),
-    Git(String),
-    Workspace,
+    Git {
+        url: String,
+        reference: GitReference,
+    },
+    /// Inherited from `[workspace.dependencies]`, where the dependency has the inner source
+    Workspace(Box<KrateSource>),
+}
+
+/// Revision of a git dependency.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GitReference {
+    #[default]
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl KrateSource {
+    pub fn from_source_id(source_id: SourceId) -> Self {
+        if source_id.is_path() {
+            let url = source_id.url();
+            KrateSource::Path(
+                url.to_file_path()
+                    .unwrap_or_else(|_| PathBuf::from(url.path())),
+            )
+        } else if source_id.is_git() {
+            let reference = match source_id.git_reference() {
+                Some(cargo::core::GitReference::Branch(branch)) => {
+                    GitReference::Branch(branch.clone())
+                }
+                Some(cargo::core::GitReference::Tag(tag)) => GitReference::Tag(tag.clone()),
+                Some(cargo::core::GitReference::Rev(rev)) => GitReference::Rev(rev.clone()),
+                _ => GitReference::DefaultBranch,
+            };
+            KrateSource::Git {
+                url: source_id.url().to_string(),
+                reference,
+            }
+        } else if source_id.is_crates_io() {
+            KrateSource::Registry
+        } else {
+            KrateSource::AlternativeRegistry(
+                source_id
+                    .alt_registry_key()
+                    .map(String::from)
+                    .unwrap_or_else(|| source_id.url().to_string()),
+            )
+        }
+    }
+
+    /// Source the dependency resolves to, through workspace inheritance.
+    pub fn resolved(&self) -> &KrateSource {
+        match self {
+            KrateSource::Workspace(source) => source.resolved(),
+            source => source,
+        }
+    }
+
+    /// Whether the dependency is on the filesystem, such as a link to a workspace member.
+    pub fn is_local(&self) -> bool {
+        matches!(self.resolved(), KrateSource::Path(_))
+    }
+}
+
+impl fmt::Display for KrateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KrateSource::Registry => write!(f, "crates.io"),
+            KrateSource::AlternativeRegistry(registry) => write!(f, "registry {}", registry),
+            KrateSource::Path(path) => write!(f, "{}", path.display()),
+            KrateSource::Git { url, reference } => match reference {
+                GitReference::DefaultBranch => write!(f, "{}", url),
+                GitReference::Branch(branch) => write!(f, "{}#branch={}", url, branch),
+                GitReference::Tag(tag) => write!(f, "{}#tag={}", url, tag),
+                GitReference::Rev(rev) => write!(f, "{}#rev={}", url, rev),
+            },
+            KrateSource::Workspace(source) => write!(f, "workspace, {}", source),
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone, Copy)]
@@ -36,6 +133,28 @@ pub enum KrateKind {
     Lib,
 }
 
+/// Table a dependency is declared in, ordered from the strongest to the weakest link.
+#[derive(
+    Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    #[default]
+    Normal,
+    Build,
+    Dev,
+}
+
+impl From<DepKind> for DependencyKind {
+    fn from(kind: DepKind) -> Self {
+        match kind {
+            DepKind::Normal => DependencyKind::Normal,
+            DepKind::Build => DependencyKind::Build,
+            DepKind::Development => DependencyKind::Dev,
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Krate {
     pub name: String,
@@ -43,6 +162,18 @@ pub struct Krate {
     pub path: KrateSource,
     pub manifest_path: Option<PathBuf>,
     pub dependencies: HashMap<String, Krate>,
+    /// How this crate is depended upon, when it is a dependency
+    pub dep_kind: DependencyKind,
+    /// Whether a workspace member has a library target
+    pub kind: KrateKind,
+    /// Features enabled on the dependency
+    pub features: Vec<String>,
+    pub default_features: bool,
+    pub optional: bool,
+    /// `cfg(..)` expression or target triple of the table declaring the dependency
+    pub target: Option<String>,
+    /// Name the dependency is imported under, when renamed
+    pub rename: Option<String>,
 }
 
 impl Krate {
@@ -58,6 +189,13 @@ impl Krate {
             path: source,
             manifest_path,
             dependencies: HashMap::new(),
+            dep_kind: DependencyKind::default(),
+            kind: KrateKind::default(),
+            features: vec![],
+            default_features: true,
+            optional: false,
+            target: None,
+            rename: None,
         }
     }
 
@@ -115,11 +253,19 @@ impl Krate {
         Ok(self)
     }
 
+    /// Creates the crate with `cargo new`, between the `pre-new` and `post-new` hooks.
     pub fn create_in_workspace(
         kind: KrateKind,
         name: String,
         path: PathBuf,
+        hooks: &Hooks,
     ) -> anyhow::Result<Self> {
+        let vars = [
+            (CRATE_ENV, name.clone()),
+            (CRATE_PATH_ENV, path.to_string_lossy().to_string()),
+        ];
+        hooks.run(HookPoint::PreNew, &vars)?;
+
         let ctx = GlobalContext::default()?;
         let is_bin = kind == KrateKind::Bin;
         let is_lib = kind == KrateKind::Lib;
@@ -136,55 +282,180 @@ impl Krate {
 
         let _ = ops::new(&opts, &ctx)?;
 
-        Self::from_path(&path.to_string_lossy().to_string())
+        let krate = Self::from_path(&path.to_string_lossy().to_string())?;
+        hooks.run(HookPoint::PostNew, &vars)?;
+
+        Ok(krate)
     }
 
     pub fn as_cargo_dependency(
         &self,
     ) -> anyhow::Result<cargo::util::toml_mut::dependency::Dependency> {
         let source = match &self.path {
-            KrateSource::Path(path) => Source::Path(PathSource::new(path)),
-            KrateSource::Workspace => Source::Workspace(WorkspaceSource::new()),
-            KrateSource::Registry => Source::Registry(RegistrySource::new(&self.version)),
-            KrateSource::Git(url) => Source::Git(GitSource::new(url)),
+            KrateSource::Path(path) => {
+                Source::Path(PathSource::new(path).set_version(&self.version))
+            }
+            KrateSource::Workspace(_) => Source::Workspace(WorkspaceSource::new()),
+            KrateSource::Registry | KrateSource::AlternativeRegistry(_) => {
+                Source::Registry(RegistrySource::new(&self.version))
+            }
+            KrateSource::Git { url, reference } => {
+                let source = GitSource::new(url);
+                Source::Git(match reference {
+                    GitReference::DefaultBranch => source,
+                    GitReference::Branch(branch) => source.set_branch(branch),
+                    GitReference::Tag(tag) => source.set_tag(tag),
+                    GitReference::Rev(rev) => source.set_rev(rev),
+                })
+            }
         };
 
-        let dep = cargo::util::toml_mut::dependency::Dependency::new(&self.name).set_source(source);
+        let mut dep =
+            cargo::util::toml_mut::dependency::Dependency::new(&self.name).set_source(source);
+        if let KrateSource::AlternativeRegistry(registry) = &self.path {
+            dep = dep.set_registry(registry);
+        }
 
         Ok(dep)
     }
 
-    pub fn add_dependency(&self, _dep: String) -> anyhow::Result<Self> {
-        todo!()
+    /// This crate as a dependency, renamed and without default features as requested.
+    pub fn as_declared_dependency(
+        &self,
+        options: &DependencyOptions,
+    ) -> anyhow::Result<cargo::util::toml_mut::dependency::Dependency> {
+        let mut dep = self.as_cargo_dependency()?;
+        if let Some(rename) = &options.rename {
+            dep = dep.set_rename(rename);
+        }
+        if options.no_default_features {
+            dep = dep.set_default_features(false);
+        }
+        Ok(dep)
     }
 
-    pub fn link_to(&self, dep: &Krate) -> anyhow::Result<()> {
-        if let Some(manifest_path) = &self.manifest_path {
-            let mut local_manifest = LocalManifest::try_new(manifest_path)?;
+    /// Declares `dep` in the manifest of this crate, updating an existing declaration in the
+    /// same table. With `options.workspace` the entry inherits `[workspace.dependencies]`.
+    pub fn add_dependency(&self, dep: &Krate, options: &DependencyOptions) -> anyhow::Result<()> {
+        options.validate()?;
+        let manifest_path = self
+            .manifest_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Crate '{}' has no manifest", self.name))?;
+        let mut local_manifest = LocalManifest::try_new(manifest_path)?;
+
+        let cargo_dep = match options.workspace {
+            true => cargo::util::toml_mut::dependency::Dependency::new(
+                options.rename.as_deref().unwrap_or(&dep.name),
+            )
+            .set_source(WorkspaceSource::new()),
+            false => dep.as_declared_dependency(options)?,
+        };
+        let cargo_dep = cargo_dep
+            .extend_features(options.features.iter().cloned())
+            .set_optional(options.optional);
 
-            let table_name = vec!["dependencies".to_string()];
-            let cargo_dep = dep.as_cargo_dependency()?;
-            println!("{:#?}", cargo_dep);
-            local_manifest.insert_into_table(&table_name, &cargo_dep)?;
-            local_manifest.write()?;
-        }
+        local_manifest.insert_into_table(&options.table_path(), &cargo_dep)?;
+        local_manifest.write()?;
 
         Ok(())
     }
 
-    pub fn from_cargo_dependency(dep: &Dependency, source: KrateSource) -> anyhow::Result<Self> {
-        let name = dep.name_in_toml().to_string();
+    /// Declares the workspace member `dep` as a path dependency of this crate. The link hooks
+    /// are run by [`Workspace::link`](crate::workspace::Workspace::link).
+    pub fn link_to(&self, dep: &Krate, options: &DependencyOptions) -> anyhow::Result<()> {
+        if !matches!(dep.path, KrateSource::Path(_)) {
+            return Err(anyhow!("Only workspace members can be linked"));
+        }
+
+        self.add_dependency(dep, options)
+    }
+
+    /// Removes `name` from every dependency table of the manifest, target-specific ones
+    /// included, along with the features enabling it. Renamed dependencies match on their key
+    /// or on their package name. Returns the removed keys.
+    pub fn remove_dependency(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let manifest_path = self
+            .manifest_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Crate '{}' has no manifest", self.name))?;
+        let content = std::fs::read_to_string(manifest_path)
+            .with_context(|| format!("Failed to read manifest at {:?}", manifest_path))?;
+        let mut document = content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse manifest at {:?}", manifest_path))?;
+
+        let mut removed: Vec<String> = vec![];
+        for table in package_dependency_tables_mut(&mut document) {
+            let keys: Vec<String> = table
+                .iter()
+                .filter(|(key, entry)| dependency_name(key, entry) == name || *key == name)
+                .map(|(key, _)| key.to_string())
+                .collect();
+            for key in keys {
+                table.remove(&key);
+                if !removed.contains(&key) {
+                    removed.push(key);
+                }
+            }
+        }
+
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+
+        // Features of optional dependencies: `name`, `dep:name`, `name/feat` and `name?/feat`
+        if let Some(features) = document
+            .get_mut("features")
+            .and_then(|features| features.as_table_like_mut())
+        {
+            for (_, values) in features.iter_mut() {
+                let Some(values) = values.as_array_mut() else {
+                    continue;
+                };
+                values.retain(|value| {
+                    let Some(value) = value.as_str() else {
+                        return true;
+                    };
+                    let target = value.strip_prefix("dep:").unwrap_or(value);
+                    let target = target.split('/').next().unwrap_or(target);
+                    let target = target.strip_suffix('?').unwrap_or(target);
+                    !removed.iter().any(|key| key == target)
+                });
+            }
+        }
+
+        write_atomic(manifest_path, document.to_string().as_bytes())
+            .with_context(|| format!("Failed to write {:?}", manifest_path))?;
+
+        Ok(removed)
+    }
+
+    pub fn from_cargo_dependency(dep: &Dependency) -> anyhow::Result<Self> {
+        let name = dep.package_name().to_string();
         let version = dep.version_req().to_string();
-        Ok(Self::new(name, version, source))
+        let source = KrateSource::from_source_id(dep.source_id());
+
+        let mut krate = Self::new(name, version, source);
+        krate.dep_kind = dep.kind().into();
+        krate.features = dep.features().iter().map(|f| f.to_string()).collect();
+        krate.default_features = dep.uses_default_features();
+        krate.optional = dep.is_optional();
+        krate.target = dep.platform().map(|platform| platform.to_string());
+        krate.rename = dep.explicit_name_in_toml().map(|name| name.to_string());
+        Ok(krate)
     }
 
     pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        Self::from_path_with(&GlobalContext::default()?, path)
+    }
+
+    /// [`Krate::from_path`] with the registries configured in `gctx`.
+    fn from_path_with(gctx: &GlobalContext, path: &str) -> anyhow::Result<Self> {
         let (_, manifest_path) = resolve_manifest_path(&PathBuf::from_str(path)?);
 
         let source_id = SourceId::for_path(&manifest_path)?;
-        let ctx = GlobalContext::default()?;
-
-        let manifest = match read_manifest(&manifest_path, source_id, &ctx)? {
+        let manifest = match read_manifest(&manifest_path, source_id, gctx)? {
             EitherManifest::Real(manifest) => manifest,
             _ => return Err(anyhow!("Failed to read manifest")),
         };
@@ -194,18 +465,409 @@ impl Krate {
         let source = KrateSource::Path(path.to_owned().into());
 
         let mut krate = Krate::new(name, version, source);
+        if manifest.targets().iter().any(|target| target.is_lib()) {
+            krate.kind = KrateKind::Lib;
+        }
+
+        // Inheritance is resolved by cargo, the raw manifest tells which entries use it
+        let inherited = inherited_dependencies(&manifest_path)?;
 
         for dep in manifest.dependencies() {
-            let dep_source = if dep.source_id().is_path() {
-                KrateSource::Path(dep.source_id().url().to_string().into())
-            } else {
-                KrateSource::Registry
-            };
+            let mut dep_krate = Krate::from_cargo_dependency(dep)?;
+            if inherited.contains(dep.name_in_toml().as_str()) {
+                let source = std::mem::take(&mut dep_krate.path);
+                dep_krate.path = KrateSource::Workspace(Box::new(source));
+            }
 
-            let dep_krate = Krate::from_cargo_dependency(dep, dep_source)?;
-            krate.dependencies.insert(dep_krate.name.clone(), dep_krate);
+            // A crate can be declared in several tables, keep its strongest link
+            match krate.dependencies.get(&dep_krate.name) {
+                Some(existing) if existing.dep_kind <= dep_krate.dep_kind => {}
+                _ => {
+                    krate.dependencies.insert(dep_krate.name.clone(), dep_krate);
+                }
+            }
         }
 
         Ok(krate)
     }
 }
+
+/// Keys of the dependencies declared with `workspace = true` in a manifest.
+fn inherited_dependencies(manifest_path: &Path) -> anyhow::Result<HashSet<String>> {
+    let content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read manifest at {:?}", manifest_path))?;
+    let mut document = content
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse manifest at {:?}", manifest_path))?;
+
+    let mut inherited = HashSet::new();
+    for table in package_dependency_tables_mut(&mut document) {
+        for (key, entry) in table.iter() {
+            if entry.get("workspace").and_then(|value| value.as_bool()) == Some(true) {
+                inherited.insert(key.to_string());
+            }
+        }
+    }
+
+    Ok(inherited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Library `name` under `root`, with `extra` appended to its manifest.
+    fn member(root: &Path, name: &str, extra: &str) -> Krate {
+        let path = root.join(name);
+        write(
+            &path.join("Cargo.toml"),
+            &format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{}",
+                name, extra
+            ),
+        );
+        write(&path.join("src/lib.rs"), "");
+        Krate::new(
+            name.to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(path),
+        )
+    }
+
+    /// Manifest entry at `path`, such as `["dependencies", "serde"]`.
+    fn entry(krate: &Krate, path: &[&str]) -> Option<toml::Value> {
+        let content = std::fs::read_to_string(krate.manifest_path.as_ref().unwrap()).unwrap();
+        let mut value = toml::Value::Table(content.parse().unwrap());
+        for key in path {
+            value = value.get(key)?.clone();
+        }
+        Some(value)
+    }
+
+    fn registry(name: &str, version: &str) -> Krate {
+        Krate::new(name.to_string(), version.to_string(), KrateSource::Registry)
+    }
+
+    #[test]
+    fn add_dependency_to_every_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = member(dir.path(), "app", "");
+
+        let options = DependencyOptions {
+            features: vec!["derive".to_string()],
+            ..Default::default()
+        };
+        app.add_dependency(&registry("serde", "1"), &options)
+            .unwrap();
+        let serde = entry(&app, &["dependencies", "serde"]).unwrap();
+        assert_eq!(serde["version"].as_str(), Some("1"));
+        assert_eq!(serde["features"], toml::Value::Array(vec!["derive".into()]));
+
+        let options = DependencyOptions {
+            kind: DependencyKind::Build,
+            ..Default::default()
+        };
+        app.add_dependency(&registry("cc", "1.0"), &options)
+            .unwrap();
+        assert!(entry(&app, &["build-dependencies", "cc"]).is_some());
+
+        let options = DependencyOptions {
+            kind: DependencyKind::Dev,
+            ..Default::default()
+        };
+        app.add_dependency(&registry("insta", "1.38"), &options)
+            .unwrap();
+        assert!(entry(&app, &["dev-dependencies", "insta"]).is_some());
+
+        let options = DependencyOptions {
+            optional: true,
+            target: Some("cfg(unix)".to_string()),
+            ..Default::default()
+        };
+        app.add_dependency(&registry("libc", "0.2"), &options)
+            .unwrap();
+        let libc = entry(&app, &["target", "cfg(unix)", "dependencies", "libc"]).unwrap();
+        assert_eq!(libc["version"].as_str(), Some("0.2"));
+        assert_eq!(libc["optional"].as_bool(), Some(true));
+        assert!(entry(&app, &["dependencies", "libc"]).is_none());
+
+        // An existing declaration is updated in place
+        app.add_dependency(
+            &registry("cc", "1.1"),
+            &DependencyOptions {
+                kind: DependencyKind::Build,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let cc = entry(&app, &["build-dependencies", "cc"]).unwrap();
+        let version = cc.get("version").unwrap_or(&cc);
+        assert_eq!(version.as_str(), Some("1.1"));
+    }
+
+    #[test]
+    fn add_workspace_dependency() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = member(dir.path(), "app", "");
+
+        let options = DependencyOptions {
+            features: vec!["derive".to_string()],
+            workspace: true,
+            ..Default::default()
+        };
+        app.add_dependency(&registry("serde", "1"), &options)
+            .unwrap();
+
+        // The requirement lives in `[workspace.dependencies]`, the member only inherits it
+        let serde = entry(&app, &["dependencies", "serde"]).unwrap();
+        assert_eq!(serde["workspace"].as_bool(), Some(true));
+        assert_eq!(serde["features"], toml::Value::Array(vec!["derive".into()]));
+        assert!(serde.get("version").is_none());
+    }
+
+    #[test]
+    fn add_dependency_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = member(dir.path(), "app", "");
+
+        let options = DependencyOptions {
+            kind: DependencyKind::Dev,
+            optional: true,
+            ..Default::default()
+        };
+        let err = app
+            .add_dependency(&registry("insta", "1"), &options)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Dev-dependencies cannot be optional");
+        assert!(entry(&app, &["dev-dependencies"]).is_none());
+
+        let err = registry("serde", "1")
+            .add_dependency(&registry("serde_derive", "1"), &Default::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Crate 'serde' has no manifest");
+    }
+
+    #[test]
+    fn declared_dependency_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = member(dir.path(), "core", "");
+
+        let dep = core.as_declared_dependency(&Default::default()).unwrap();
+        assert_eq!(dep.rename, None);
+        assert_eq!(dep.default_features, None);
+
+        let options = DependencyOptions {
+            rename: Some("base".to_string()),
+            no_default_features: true,
+            ..Default::default()
+        };
+        let dep = core.as_declared_dependency(&options).unwrap();
+        assert_eq!(dep.name, "core");
+        assert_eq!(dep.rename.as_deref(), Some("base"));
+        assert_eq!(dep.default_features, Some(false));
+    }
+
+    #[test]
+    fn link_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = member(dir.path(), "core", "");
+        let app = member(dir.path(), "app", "");
+
+        app.link_to(&core, &Default::default()).unwrap();
+        let link = entry(&app, &["dependencies", "core"]).unwrap();
+        assert_eq!(link["path"].as_str(), Some("../core"));
+        assert_eq!(link["version"].as_str(), Some("0.1.0"));
+
+        let options = DependencyOptions {
+            kind: DependencyKind::Dev,
+            features: vec!["testing".to_string()],
+            no_default_features: true,
+            rename: Some("base".to_string()),
+            ..Default::default()
+        };
+        app.link_to(&core, &options).unwrap();
+        let link = entry(&app, &["dev-dependencies", "base"]).unwrap();
+        assert_eq!(link["package"].as_str(), Some("core"));
+        assert_eq!(link["path"].as_str(), Some("../core"));
+        assert_eq!(link["default-features"].as_bool(), Some(false));
+        assert_eq!(link["features"], toml::Value::Array(vec!["testing".into()]));
+
+        let err = app
+            .link_to(&registry("serde", "1"), &Default::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Only workspace members can be linked");
+        assert!(entry(&app, &["dependencies", "serde"]).is_none());
+    }
+
+    #[test]
+    fn remove_dependency_from_every_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = member(
+            dir.path(),
+            "app",
+            r#"
+[dependencies]
+core = { path = "../core", optional = true }
+serde = "1"
+
+[build-dependencies]
+core = { path = "../core" }
+
+[dev-dependencies]
+base = { package = "core", path = "../core" }
+
+[target.'cfg(unix)'.dependencies]
+core = { path = "../core" }
+
+[target.'cfg(windows)'.dev-dependencies]
+core = { path = "../core" }
+
+[features]
+default = ["core", "serde"]
+extra = ["dep:core", "core/std", "core?/testing", "base/testing", "serde/derive"]
+"#,
+        );
+
+        // Renamed entries match on their package name
+        let mut removed = app.remove_dependency("core").unwrap();
+        removed.sort();
+        assert_eq!(removed, ["base", "core"]);
+
+        for table in [
+            &["dependencies"][..],
+            &["build-dependencies"],
+            &["dev-dependencies"],
+            &["target", "cfg(unix)", "dependencies"],
+            &["target", "cfg(windows)", "dev-dependencies"],
+        ] {
+            let table = entry(&app, table).unwrap();
+            assert!(table.get("core").is_none(), "{:?}", table);
+            assert!(table.get("base").is_none(), "{:?}", table);
+        }
+        assert!(entry(&app, &["dependencies", "serde"]).is_some());
+
+        // Features enabling the removed dependency go along, the others stay
+        let features = |name: &str| entry(&app, &["features", name]).unwrap();
+        assert_eq!(
+            features("default"),
+            toml::Value::Array(vec!["serde".into()])
+        );
+        assert_eq!(
+            features("extra"),
+            toml::Value::Array(vec!["serde/derive".into()])
+        );
+
+        // Nothing to remove leaves the manifest untouched
+        let manifest = app.manifest_path.as_ref().unwrap();
+        let before = std::fs::read_to_string(manifest).unwrap();
+        assert!(app.remove_dependency("core").unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(manifest).unwrap(), before);
+    }
+
+    #[test]
+    fn dependency_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            &dir.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["app", "core"]
+resolver = "2"
+
+[workspace.dependencies]
+tokio = { version = "1", features = ["rt"] }
+"#,
+        );
+        member(dir.path(), "core", "");
+        member(
+            dir.path(),
+            "app",
+            r#"
+[dependencies]
+core = { path = "../core" }
+serde = { version = "1", default-features = false, features = ["derive"] }
+json = { package = "serde_json", version = "1", optional = true }
+private = { version = "0.3", registry = "private" }
+gitdep = { git = "https://github.com/peniche/gitdep", tag = "v1.0.0" }
+tokio = { workspace = true, features = ["macros"] }
+
+[build-dependencies]
+cc = "1"
+
+[dev-dependencies]
+serde = "1"
+cc = "1"
+insta = { git = "https://github.com/mitsuhiko/insta" }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#,
+        );
+
+        // The alternative registry is configured in the context, not in the process
+        let mut gctx = GlobalContext::new(
+            cargo::core::Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        gctx.configure(
+            0,
+            true,
+            None,
+            false,
+            false,
+            false,
+            &None,
+            &[],
+            &["registries.private.index=\"https://example.com/index\"".to_string()],
+        )
+        .unwrap();
+
+        let app = Krate::from_path_with(&gctx, &dir.path().join("app").to_string_lossy()).unwrap();
+        assert_eq!(app.name, "app");
+        assert_eq!(app.kind, KrateKind::Lib);
+        let source = |name: &str| app.dependencies[name].path.to_string();
+
+        assert_eq!(
+            source("core"),
+            dir.path().join("core").display().to_string()
+        );
+        assert!(app.dependencies["core"].path.is_local());
+        assert_eq!(source("serde"), "crates.io");
+        assert_eq!(source("private"), "registry private");
+        assert_eq!(
+            source("gitdep"),
+            "https://github.com/peniche/gitdep#tag=v1.0.0"
+        );
+        assert_eq!(source("insta"), "https://github.com/mitsuhiko/insta");
+        assert_eq!(source("tokio"), "workspace, crates.io");
+        assert_eq!(source("libc"), "crates.io");
+
+        // Declared in several tables, the strongest link is kept with its own options
+        let serde = &app.dependencies["serde"];
+        assert_eq!(serde.dep_kind, DependencyKind::Normal);
+        assert!(!serde.default_features);
+        assert_eq!(serde.features, ["derive"]);
+        assert_eq!(app.dependencies["cc"].dep_kind, DependencyKind::Build);
+        assert_eq!(app.dependencies["insta"].dep_kind, DependencyKind::Dev);
+
+        let json = &app.dependencies["serde_json"];
+        assert_eq!(json.rename.as_deref(), Some("json"));
+        assert!(json.optional);
+        assert!(!app.dependencies.contains_key("json"));
+
+        let mut tokio = app.dependencies["tokio"].features.clone();
+        tokio.sort();
+        assert_eq!(tokio, ["macros", "rt"]);
+        assert_eq!(
+            app.dependencies["libc"].target.as_deref(),
+            Some("cfg(unix)")
+        );
+        assert_eq!(app.dependencies["serde"].target, None);
+    }
+}
@@ -4,8 +4,15 @@ use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
 
 pub mod config;
+pub mod filter;
+pub mod git;
+pub mod graph;
 pub mod krate;
 pub mod log;
+pub mod plugin;
+pub mod publish;
+pub mod registry;
+pub mod release;
 pub mod workspace;
 
 pub fn resolve_manifest_path(path: &PathBuf) -> (PathBuf, PathBuf) {
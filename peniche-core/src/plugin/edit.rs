@@ -0,0 +1,295 @@
+use anyhow::{anyhow, Context as _};
+use cargo_util::paths::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use toml_edit::{DocumentMut, InlineTable, Item, Key, TableLike, Value};
+
+use crate::workspace::Workspace;
+
+/// Keys plugins cannot edit, nor the tables containing them: build scripts and native
+/// libraries run or link code at build time, `[patch]` and `[replace]` swap dependencies.
+const PROTECTED_KEYS: &[&[&str]] = &[
+    &["package", "build"],
+    &["package", "links"],
+    &["patch"],
+    &["replace"],
+];
+
+/// A manifest change proposed by a WebAssembly plugin. Plugins cannot write files, peniche
+/// applies the edits itself once the plugin exits successfully.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum ManifestEdit {
+    /// Sets a dotted key of a member's manifest (`package.description`,
+    /// `target.'cfg(unix)'.dependencies.libc`), missing tables being created
+    Set {
+        krate: String,
+        key: String,
+        value: serde_json::Value,
+    },
+    /// Removes a dotted key of a member's manifest, if present
+    Remove { krate: String, key: String },
+}
+
+impl ManifestEdit {
+    pub fn krate(&self) -> &str {
+        match self {
+            ManifestEdit::Set { krate, .. } | ManifestEdit::Remove { krate, .. } => krate,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        match self {
+            ManifestEdit::Set { key, .. } | ManifestEdit::Remove { key, .. } => key,
+        }
+    }
+
+    /// Fails when the key is invalid or protected.
+    pub fn check(&self) -> anyhow::Result<()> {
+        self.path().map(|_| ())
+    }
+
+    fn path(&self) -> anyhow::Result<Vec<Key>> {
+        let path = Key::parse(self.key())
+            .map_err(|err| anyhow!("Invalid key '{}': {}", self.key(), err))?;
+        let names: Vec<&str> = path.iter().map(Key::get).collect();
+
+        let protected = PROTECTED_KEYS
+            .iter()
+            .find(|protected| protected.starts_with(&names) || names.starts_with(protected));
+        if let Some(protected) = protected {
+            return Err(anyhow!(
+                "Plugins cannot edit '{}' of a manifest",
+                protected.join(".")
+            ));
+        }
+
+        Ok(path)
+    }
+
+    fn apply(&self, document: &mut DocumentMut) -> anyhow::Result<()> {
+        let path = self.path()?;
+        let (last, parents) = path
+            .split_last()
+            .ok_or_else(|| anyhow!("Empty manifest key"))?;
+
+        match self {
+            ManifestEdit::Set { value, .. } => {
+                let mut table: &mut dyn TableLike = document.as_table_mut();
+                for key in parents {
+                    table = table
+                        .entry(key.get())
+                        .or_insert(toml_edit::table())
+                        .as_table_like_mut()
+                        .ok_or_else(|| anyhow!("'{}' is not a table", key.get()))?;
+                }
+                table.insert(last.get(), Item::Value(toml_value(value)?));
+            }
+            ManifestEdit::Remove { .. } => {
+                let mut table: &mut dyn TableLike = document.as_table_mut();
+                for key in parents {
+                    match table
+                        .get_mut(key.get())
+                        .and_then(|item| item.as_table_like_mut())
+                    {
+                        Some(child) => table = child,
+                        None => return Ok(()),
+                    }
+                }
+                table.remove(last.get());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ManifestEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestEdit::Set { krate, key, value } => {
+                write!(f, "{}: set {} = {}", krate, key, value)
+            }
+            ManifestEdit::Remove { krate, key } => write!(f, "{}: remove {}", krate, key),
+        }
+    }
+}
+
+/// Applies the edits to the manifests of the workspace members, returning the written
+/// manifests. Every edit is checked before anything is written.
+pub fn apply_edits(ws: &Workspace, edits: &[ManifestEdit]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut documents: BTreeMap<PathBuf, DocumentMut> = BTreeMap::new();
+    for edit in edits {
+        let manifest_path = ws
+            .crates
+            .get(edit.krate())
+            .and_then(|krate| krate.manifest_path.clone())
+            .ok_or_else(|| anyhow!("Crate '{}' is not a workspace member", edit.krate()))?;
+
+        if !documents.contains_key(&manifest_path) {
+            let content = std::fs::read_to_string(&manifest_path)
+                .with_context(|| format!("Failed to read manifest at {:?}", manifest_path))?;
+            let document = content
+                .parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse manifest at {:?}", manifest_path))?;
+            documents.insert(manifest_path.clone(), document);
+        }
+
+        let document = documents.get_mut(&manifest_path).expect("loaded above");
+        edit.apply(document)
+            .with_context(|| format!("Failed to apply edit '{}'", edit))?;
+    }
+
+    for (path, document) in &documents {
+        write_atomic(path, document.to_string().as_bytes())
+            .with_context(|| format!("Failed to write updated Cargo.toml to {:?}", path))?;
+    }
+
+    Ok(documents.into_keys().collect())
+}
+
+/// TOML counterpart of a JSON value. TOML has no null.
+fn toml_value(value: &serde_json::Value) -> anyhow::Result<Value> {
+    Ok(match value {
+        serde_json::Value::Null => return Err(anyhow!("TOML has no null value")),
+        serde_json::Value::Bool(value) => (*value).into(),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(value) => value.into(),
+            None => number
+                .as_f64()
+                .ok_or_else(|| anyhow!("Unsupported number {}", number))?
+                .into(),
+        },
+        serde_json::Value::String(value) => value.as_str().into(),
+        serde_json::Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(toml_value)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        serde_json::Value::Object(entries) => {
+            let mut table = InlineTable::new();
+            for (key, value) in entries {
+                table.insert(key, toml_value(value)?);
+            }
+            Value::InlineTable(table)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MANIFEST: &str = r#"[package]
+name = "core" # the core crate
+version = "0.1.0"
+
+[dependencies]
+rand = "0.8"
+serde = { version = "1", features = ["derive"] }
+"#;
+
+    fn apply(edit: ManifestEdit) -> anyhow::Result<String> {
+        let mut document: DocumentMut = MANIFEST.parse().unwrap();
+        edit.apply(&mut document)?;
+        Ok(document.to_string())
+    }
+
+    fn set(key: &str, value: serde_json::Value) -> ManifestEdit {
+        ManifestEdit::Set {
+            krate: "core".to_string(),
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    fn remove(key: &str) -> ManifestEdit {
+        ManifestEdit::Remove {
+            krate: "core".to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    #[test]
+    fn set_keeps_the_rest_of_the_manifest() {
+        let manifest = apply(set("package.license", json!("MIT"))).unwrap();
+        assert!(manifest.contains("license = \"MIT\""));
+        assert!(manifest.contains("name = \"core\" # the core crate"));
+
+        let manifest = apply(set("dependencies.serde.features", json!(["derive", "rc"]))).unwrap();
+        let document: DocumentMut = manifest.parse().unwrap();
+        let features: Vec<&str> = document["dependencies"]["serde"]["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|feature| feature.as_str())
+            .collect();
+        assert_eq!(features, ["derive", "rc"]);
+        assert_eq!(
+            document["dependencies"]["serde"]["version"].as_str(),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn set_creates_missing_tables() {
+        let manifest = apply(set(
+            "target.'cfg(unix)'.dependencies.libc",
+            json!({ "version": "0.2", "optional": true }),
+        ))
+        .unwrap();
+        let document: DocumentMut = manifest.parse().unwrap();
+        let libc = &document["target"]["cfg(unix)"]["dependencies"]["libc"];
+        assert_eq!(libc["version"].as_str(), Some("0.2"));
+        assert_eq!(libc["optional"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn set_rejects_invalid_edits() {
+        assert!(apply(set("package.name.first", json!("core"))).is_err());
+        assert!(apply(set("package.description", json!(null))).is_err());
+        assert!(apply(set("package..name", json!("core"))).is_err());
+    }
+
+    #[test]
+    fn remove_existing_and_missing_keys() {
+        let manifest = apply(remove("dependencies.rand")).unwrap();
+        assert!(!manifest.contains("rand"));
+        assert!(manifest.contains("serde"));
+
+        assert_eq!(apply(remove("dev-dependencies.rand")).unwrap(), MANIFEST);
+        assert_eq!(apply(remove("package.license")).unwrap(), MANIFEST);
+    }
+
+    #[test]
+    fn protected_keys_are_rejected() {
+        for edit in [
+            set("package.build", json!("build.rs")),
+            set("package.links", json!("z")),
+            set("package", json!({ "name": "core", "build": "build.rs" })),
+            set("patch.crates-io.rand.path", json!("../rand")),
+            remove("replace"),
+            remove("package"),
+        ] {
+            assert!(edit.check().is_err(), "{}", edit);
+            assert!(apply(edit).is_err());
+        }
+
+        set("package.description", json!("Core")).check().unwrap();
+        set("dependencies.cc", json!("1")).check().unwrap();
+    }
+
+    #[test]
+    fn edits_are_read_from_json() {
+        let edit: ManifestEdit = serde_json::from_str(
+            r#"{ "op": "remove", "krate": "core", "key": "dependencies.rand" }"#,
+        )
+        .unwrap();
+        assert_eq!(edit, remove("dependencies.rand"));
+        assert_eq!(edit.to_string(), "core: remove dependencies.rand");
+    }
+}
@@ -0,0 +1,420 @@
+use anyhow::{anyhow, Context as _};
+use colored::Colorize as _;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::Command;
+use crate::error_msg;
+use crate::plugin::find_plugin;
+use crate::workspace::Workspace;
+
+/// Environment variable holding the name of the hook point being run.
+pub const HOOK_ENV: &str = "PENICHE_HOOK";
+
+/// Environment variable holding the name of the crate the hook runs for.
+pub const CRATE_ENV: &str = "PENICHE_CRATE";
+
+/// Environment variable holding the directory of the crate the hook runs for.
+pub const CRATE_PATH_ENV: &str = "PENICHE_CRATE_PATH";
+
+/// Environment variable holding the name of the linked dependency.
+pub const DEPENDENCY_ENV: &str = "PENICHE_DEPENDENCY";
+
+/// Environment variable holding the released crates, as space-separated `name@version`.
+pub const RELEASES_ENV: &str = "PENICHE_RELEASES";
+
+/// Points of the crate lifecycle where hooks run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HookPoint {
+    PreNew,
+    PostNew,
+    PreLink,
+    PostLink,
+    PreDelete,
+    PostDelete,
+    PreRelease,
+    PostRelease,
+}
+
+impl HookPoint {
+    pub const ALL: [HookPoint; 8] = [
+        HookPoint::PreNew,
+        HookPoint::PostNew,
+        HookPoint::PreLink,
+        HookPoint::PostLink,
+        HookPoint::PreDelete,
+        HookPoint::PostDelete,
+        HookPoint::PreRelease,
+        HookPoint::PostRelease,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookPoint::PreNew => "pre-new",
+            HookPoint::PostNew => "post-new",
+            HookPoint::PreLink => "pre-link",
+            HookPoint::PostLink => "post-link",
+            HookPoint::PreDelete => "pre-delete",
+            HookPoint::PostDelete => "post-delete",
+            HookPoint::PreRelease => "pre-release",
+            HookPoint::PostRelease => "post-release",
+        }
+    }
+
+    /// Whether the hook runs before the operation, a failure aborting it.
+    pub fn is_pre(&self) -> bool {
+        matches!(
+            self,
+            HookPoint::PreNew | HookPoint::PreLink | HookPoint::PreDelete | HookPoint::PreRelease
+        )
+    }
+}
+
+impl fmt::Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HookPoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HookPoint::ALL
+            .into_iter()
+            .find(|point| point.name() == s)
+            .ok_or_else(|| anyhow!("Unknown hook point '{}'", s))
+    }
+}
+
+/// What a hook runs: a `[cmd]` command (`cmd:<name>`) or a plugin (`plugin:<name>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookAction {
+    Command(String),
+    Plugin(String),
+}
+
+impl fmt::Display for HookAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookAction::Command(name) => write!(f, "cmd:{}", name),
+            HookAction::Plugin(name) => write!(f, "plugin:{}", name),
+        }
+    }
+}
+
+impl FromStr for HookAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let action = match s.split_once(':') {
+            Some(("cmd", name)) => HookAction::Command(name.to_string()),
+            Some(("plugin", name)) => HookAction::Plugin(name.to_string()),
+            _ => {
+                return Err(anyhow!(
+                    "Invalid hook '{}', expected 'cmd:<name>' or 'plugin:<name>'",
+                    s
+                ))
+            }
+        };
+
+        match &action {
+            HookAction::Command(name) | HookAction::Plugin(name) if name.is_empty() => {
+                Err(anyhow!("Invalid hook '{}', the name is missing", s))
+            }
+            _ => Ok(action),
+        }
+    }
+}
+
+/// Hooks configured under `[hooks]`, ready to run. The default value runs nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    actions: BTreeMap<HookPoint, Vec<HookAction>>,
+    commands: HashMap<String, Command>,
+    workspace_root: Option<PathBuf>,
+    assume_yes: bool,
+}
+
+impl Hooks {
+    /// Parses the `[hooks]` table, checking that the referenced commands exist. Plugins are
+    /// looked up when they run, in the workspace root and on `PATH`.
+    pub fn new(
+        hooks: &BTreeMap<String, Vec<String>>,
+        commands: &HashMap<String, Command>,
+        workspace_root: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let mut actions = BTreeMap::new();
+        for (point, entries) in hooks {
+            let point: HookPoint = point.parse()?;
+            let entries =
+                entries
+                    .iter()
+                    .map(|entry| {
+                        let action: HookAction = entry
+                            .parse()
+                            .with_context(|| format!("Invalid '{}' hooks", point))?;
+                        match &action {
+                            HookAction::Command(name) if !commands.contains_key(name) => Err(
+                                anyhow!("Hook '{}' refers to an unknown command '{}'", point, name),
+                            ),
+                            _ => Ok(action),
+                        }
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+            actions.insert(point, entries);
+        }
+
+        Ok(Self {
+            actions,
+            commands: commands.clone(),
+            workspace_root,
+            assume_yes: false,
+        })
+    }
+
+    /// Applies the manifest edits proposed by WebAssembly plugins without asking.
+    pub fn assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Runs the hooks of `point` in order, with `vars` in their environment. Pre-hooks stop at
+    /// the first failure. Post-hooks all run, the failures being reported afterwards since
+    /// the operation already happened.
+    pub fn run(&self, point: HookPoint, vars: &[(&str, String)]) -> anyhow::Result<()> {
+        let Some(actions) = self.actions.get(&point) else {
+            return Ok(());
+        };
+
+        let mut env: Vec<(&str, String)> = vec![(HOOK_ENV, point.name().to_string())];
+        env.extend(vars.iter().cloned());
+
+        let mut failures = vec![];
+        for action in actions {
+            if let Err(err) = self.run_action(point, action, &env) {
+                if point.is_pre() {
+                    return Err(err.context(format!("Hook '{}' failed", point)));
+                }
+                error_msg!("{:#}", err);
+                failures.push(action.to_string());
+            }
+        }
+
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("Hook '{}' failed: {}", point, failures.join(", "))),
+        }
+    }
+
+    fn run_action(
+        &self,
+        point: HookPoint,
+        action: &HookAction,
+        env: &[(&str, String)],
+    ) -> anyhow::Result<()> {
+        let code = match action {
+            HookAction::Command(name) => {
+                let command = self
+                    .commands
+                    .get(name)
+                    .ok_or_else(|| anyhow!("Unknown command '{}'", name))?;
+                command
+                    .process()?
+                    .envs(env.iter().cloned())
+                    .status()
+                    .with_context(|| format!("Failed to run command '{}'", name))?
+                    .code()
+                    // Killed by a signal
+                    .unwrap_or(1)
+            }
+            HookAction::Plugin(name) => {
+                let plugin = find_plugin(name, self.workspace_root.as_deref())
+                    .ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+                // Loaded afresh so post-hooks see the result of the operation
+                let ws = match &self.workspace_root {
+                    Some(root) => Some(Workspace::from_path(&root.to_string_lossy())?),
+                    None => None,
+                };
+                plugin.run_with_env(
+                    &[point.name().to_string()],
+                    ws.as_ref(),
+                    env,
+                    self.assume_yes,
+                )?
+            }
+        };
+
+        match code {
+            0 => Ok(()),
+            code => Err(anyhow!("{} exited with code {}", action, code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Shell;
+    use std::path::Path;
+
+    /// `[cmd]` entry running `script` with `sh`.
+    fn command(name: &str, script: &str) -> Command {
+        Command::Simple {
+            key: name.to_string(),
+            colored_key: Default::default(),
+            command: script.to_string(),
+            working_dir: None,
+            env: None,
+            shell: Shell::Sh,
+        }
+    }
+
+    /// Commands appending their name and hook environment to `log`, and `fail` exiting with
+    /// code 3.
+    fn commands(log: &Path) -> HashMap<String, Command> {
+        let mut commands = HashMap::new();
+        for name in ["first", "second", "third"] {
+            let script = format!(
+                "echo \"{} $PENICHE_HOOK $PENICHE_CRATE\" >> '{}'",
+                name,
+                log.display()
+            );
+            commands.insert(name.to_string(), command(name, &script));
+        }
+        commands.insert("fail".to_string(), command("fail", "exit 3"));
+        commands
+    }
+
+    /// Hooks running `actions` at `point`.
+    fn configured(point: &str, actions: &[&str], commands: &HashMap<String, Command>) -> Hooks {
+        let actions = actions.iter().map(|action| action.to_string()).collect();
+        let entries = BTreeMap::from([(point.to_string(), actions)]);
+        Hooks::new(&entries, commands, None).unwrap()
+    }
+
+    fn read_log(log: &Path) -> Vec<String> {
+        std::fs::read_to_string(log)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn parse_hook_points() {
+        for point in HookPoint::ALL {
+            assert_eq!(point.name().parse::<HookPoint>().unwrap(), point);
+        }
+        assert!(HookPoint::PreLink.is_pre());
+        assert!(!HookPoint::PostRelease.is_pre());
+
+        let err = "pre-build".parse::<HookPoint>().unwrap_err();
+        assert_eq!(err.to_string(), "Unknown hook point 'pre-build'");
+    }
+
+    #[test]
+    fn parse_hook_actions() {
+        assert_eq!(
+            "cmd:lint".parse::<HookAction>().unwrap(),
+            HookAction::Command("lint".to_string())
+        );
+        assert_eq!(
+            "plugin:audit".parse::<HookAction>().unwrap(),
+            HookAction::Plugin("audit".to_string())
+        );
+        assert_eq!(
+            HookAction::Plugin("audit".to_string()).to_string(),
+            "plugin:audit"
+        );
+
+        for action in ["lint", "script:lint", ":lint"] {
+            let err = action.parse::<HookAction>().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Invalid hook '{}', expected 'cmd:<name>' or 'plugin:<name>'",
+                    action
+                )
+            );
+        }
+        let err = "cmd:".parse::<HookAction>().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid hook 'cmd:', the name is missing");
+    }
+
+    #[test]
+    fn hooks_check_their_configuration() {
+        let commands = HashMap::from([("lint".to_string(), command("lint", "true"))]);
+        let hooks = |point: &str, action: &str| {
+            let entries = BTreeMap::from([(point.to_string(), vec![action.to_string()])]);
+            Hooks::new(&entries, &commands, None)
+        };
+
+        assert!(hooks("pre-new", "cmd:lint").is_ok());
+        // Plugins are only looked up when they run
+        assert!(hooks("pre-new", "plugin:not-installed").is_ok());
+
+        let err = hooks("pre-new", "cmd:build").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Hook 'pre-new' refers to an unknown command 'build'"
+        );
+        let err = hooks("pre-new", "lint").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid 'pre-new' hooks");
+        assert!(hooks("before-new", "cmd:lint").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pre_hooks_stop_at_the_first_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("hooks.log");
+        let commands = commands(&log);
+        let vars = [(CRATE_ENV, "core".to_string())];
+
+        let hooks = configured("pre-new", &["cmd:first", "cmd:second"], &commands);
+        hooks.run(HookPoint::PreNew, &vars).unwrap();
+        assert_eq!(
+            read_log(&log),
+            ["first pre-new core", "second pre-new core"]
+        );
+        // No hook configured for this point
+        hooks.run(HookPoint::PreLink, &vars).unwrap();
+
+        std::fs::remove_file(&log).unwrap();
+        let hooks = configured(
+            "pre-new",
+            &["cmd:first", "cmd:fail", "cmd:second"],
+            &commands,
+        );
+        let err = hooks.run(HookPoint::PreNew, &vars).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Hook 'pre-new' failed: cmd:fail exited with code 3"
+        );
+        assert_eq!(read_log(&log), ["first pre-new core"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn post_hooks_collect_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("hooks.log");
+        let commands = commands(&log);
+        let vars = [(CRATE_ENV, "core".to_string())];
+
+        let actions = ["cmd:fail", "cmd:first", "plugin:not-installed", "cmd:third"];
+        let hooks = configured("post-new", &actions, &commands);
+        let err = hooks.run(HookPoint::PostNew, &vars).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Hook 'post-new' failed: cmd:fail, plugin:not-installed"
+        );
+        assert_eq!(
+            read_log(&log),
+            ["first post-new core", "third post-new core"]
+        );
+    }
+}
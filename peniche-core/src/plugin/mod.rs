@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Context as _};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use crate::workspace::Workspace;
+
+pub mod edit;
+pub mod hooks;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Prefix of plugin executables, `peniche-<name>` being run as `peniche <name>`.
+pub const PLUGIN_PREFIX: &str = "peniche-";
+
+/// Plugin directory, relative to the workspace root.
+pub const PLUGIN_DIR: &str = ".peniche/plugins";
+
+/// Environment variable holding the workspace model as JSON, or `-` when it is written on
+/// the standard input of the plugin instead.
+pub const WORKSPACE_ENV: &str = "PENICHE_WORKSPACE";
+
+/// Environment variable holding the path of the workspace root.
+pub const WORKSPACE_ROOT_ENV: &str = "PENICHE_WORKSPACE_ROOT";
+
+/// Larger models are written on stdin, environment variables are limited in size.
+const MAX_ENV_JSON_LEN: usize = 64 * 1024;
+
+/// Extension of WebAssembly plugins, which are only looked up in the workspace.
+pub const WASM_EXTENSION: &str = "wasm";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginOrigin {
+    /// `.peniche/plugins/` of the workspace
+    Workspace,
+    /// A directory of `PATH`
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginKind {
+    /// A native executable, trusted like any other program
+    Executable,
+    /// A WASI module, sandboxed and only able to propose manifest edits
+    Wasm,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+    pub origin: PluginOrigin,
+    pub kind: PluginKind,
+}
+
+impl Plugin {
+    /// Runs the plugin with `args`, passing it the workspace model when there is one, and
+    /// returns its exit code. The manifest edits proposed by a WebAssembly plugin are
+    /// confirmed on the terminal unless `assume_yes` is set.
+    pub fn run(
+        &self,
+        args: &[String],
+        ws: Option<&Workspace>,
+        assume_yes: bool,
+    ) -> anyhow::Result<i32> {
+        self.run_with_env(args, ws, &[], assume_yes)
+    }
+
+    /// Same as `run`, with extra environment variables.
+    pub fn run_with_env(
+        &self,
+        args: &[String],
+        ws: Option<&Workspace>,
+        env: &[(&str, String)],
+        assume_yes: bool,
+    ) -> anyhow::Result<i32> {
+        match self.kind {
+            PluginKind::Executable => self.run_executable(args, ws, env),
+            PluginKind::Wasm => self.run_wasm(args, ws, env, assume_yes),
+        }
+    }
+
+    fn run_executable(
+        &self,
+        args: &[String],
+        ws: Option<&Workspace>,
+        env: &[(&str, String)],
+    ) -> anyhow::Result<i32> {
+        let mut command = std::process::Command::new(&self.path);
+        command.args(args).envs(env.iter().cloned());
+
+        let mut stdin_json = None;
+        if let Some(ws) = ws {
+            let json = serde_json::to_string(ws)?;
+            command.env(WORKSPACE_ROOT_ENV, &ws.path);
+            if json.len() > MAX_ENV_JSON_LEN {
+                command.env(WORKSPACE_ENV, "-").stdin(Stdio::piped());
+                stdin_json = Some(json);
+            } else {
+                command.env(WORKSPACE_ENV, json);
+            }
+        }
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to run plugin {:?}", self.path))?;
+
+        if let (Some(json), Some(mut stdin)) = (stdin_json, child.stdin.take()) {
+            // The plugin may exit without reading its input
+            let _ = stdin.write_all(json.as_bytes());
+        }
+
+        // Killed by a signal
+        Ok(child.wait()?.code().unwrap_or(1))
+    }
+
+    /// Runs the module in its sandbox, then applies the manifest edits it proposed if it
+    /// succeeded and they are confirmed.
+    #[cfg(feature = "wasm")]
+    fn run_wasm(
+        &self,
+        args: &[String],
+        ws: Option<&Workspace>,
+        env: &[(&str, String)],
+        assume_yes: bool,
+    ) -> anyhow::Result<i32> {
+        let run = wasm::run_module(&self.path, &self.name, args, ws, env)?;
+        if run.code != 0 || run.edits.is_empty() {
+            return Ok(run.code);
+        }
+
+        let ws = ws.ok_or_else(|| {
+            anyhow!(
+                "Plugin '{}' proposed manifest edits outside of a workspace",
+                self.name
+            )
+        })?;
+        use colored::Colorize as _;
+        for edit in &run.edits {
+            crate::info_msg!("{} proposed {}", self.name, edit);
+        }
+        if !assume_yes && !self.confirm_edits()? {
+            crate::info_msg!("Edits of '{}' discarded", self.name);
+            return Ok(run.code);
+        }
+        edit::apply_edits(ws, &run.edits)?;
+
+        Ok(run.code)
+    }
+
+    #[cfg(feature = "wasm")]
+    fn confirm_edits(&self) -> anyhow::Result<bool> {
+        use std::io::IsTerminal as _;
+
+        if !std::io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "Plugin '{}' proposed manifest edits, pass --yes to apply them outside of a terminal",
+                self.name
+            ));
+        }
+
+        print!("Apply these edits? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn run_wasm(
+        &self,
+        _args: &[String],
+        _ws: Option<&Workspace>,
+        _env: &[(&str, String)],
+        _assume_yes: bool,
+    ) -> anyhow::Result<i32> {
+        Err(anyhow!(
+            "Plugin '{}' is a WebAssembly module, but peniche was built without the 'wasm' feature",
+            self.name
+        ))
+    }
+}
+
+/// Plugins found in `.peniche/plugins/` of the workspace and on `PATH`, sorted by name.
+/// Workspace plugins shadow the ones on `PATH`, and earlier `PATH` entries the later ones.
+pub fn discover_plugins(workspace_root: Option<&Path>) -> Vec<Plugin> {
+    let mut dirs: Vec<(PathBuf, PluginOrigin)> = vec![];
+    if let Some(root) = workspace_root {
+        dirs.push((root.join(PLUGIN_DIR), PluginOrigin::Workspace));
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        dirs.extend(std::env::split_paths(&path).map(|dir| (dir, PluginOrigin::Path)));
+    }
+
+    let mut plugins: BTreeMap<String, Plugin> = BTreeMap::new();
+    for (dir, origin) in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        let mut found: Vec<(String, PathBuf, PluginKind)> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter_map(|path| {
+                let (name, kind) = plugin_name(&path, origin)?;
+                Some((name, path, kind))
+            })
+            .filter(|(_, path, kind)| match kind {
+                PluginKind::Executable => is_executable(path),
+                PluginKind::Wasm => path.is_file(),
+            })
+            .collect();
+        found.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+        for (name, path, kind) in found {
+            plugins.entry(name.clone()).or_insert(Plugin {
+                name,
+                path,
+                origin,
+                kind,
+            });
+        }
+    }
+
+    plugins.into_values().collect()
+}
+
+pub fn find_plugin(name: &str, workspace_root: Option<&Path>) -> Option<Plugin> {
+    discover_plugins(workspace_root)
+        .into_iter()
+        .find(|plugin| plugin.name == name)
+}
+
+/// Name and kind of the plugin behind a file. The prefix is optional in the plugin directory
+/// of the workspace, where `.wasm` modules are plugins too. Only the executable and `.wasm`
+/// extensions are stripped, other dots are part of the name (`peniche-foo.v2` is `foo.v2`).
+fn plugin_name(path: &Path, origin: PluginOrigin) -> Option<(String, PluginKind)> {
+    let file_name = path.file_name()?.to_str()?;
+    let wasm_suffix = format!(".{}", WASM_EXTENSION);
+    let (file_name, kind) = match (file_name.strip_suffix(&wasm_suffix), origin) {
+        (Some(stem), PluginOrigin::Workspace) => (stem, PluginKind::Wasm),
+        (Some(_), PluginOrigin::Path) => return None,
+        (None, _) => match std::env::consts::EXE_SUFFIX {
+            "" => (file_name, PluginKind::Executable),
+            suffix => (file_name.strip_suffix(suffix)?, PluginKind::Executable),
+        },
+    };
+
+    let name = match (file_name.strip_prefix(PLUGIN_PREFIX), origin) {
+        (Some(name), _) => name,
+        (None, PluginOrigin::Workspace) => file_name,
+        (None, PluginOrigin::Path) => return None,
+    };
+
+    // Hidden files, such as `.gitkeep`, are not plugins
+    (!name.is_empty() && !name.starts_with('.')).then(|| (name.to_string(), kind))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(file_name: &str, origin: PluginOrigin) -> Option<(String, PluginKind)> {
+        plugin_name(Path::new("/plugins").join(file_name).as_path(), origin)
+    }
+
+    fn executable(name: &str) -> String {
+        format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+    }
+
+    #[test]
+    fn plugin_names_keep_their_dots() {
+        assert_eq!(
+            name(&executable("peniche-foo.bar"), PluginOrigin::Path),
+            Some(("foo.bar".to_string(), PluginKind::Executable))
+        );
+        assert_eq!(
+            name(&executable("foo.v2"), PluginOrigin::Workspace),
+            Some(("foo.v2".to_string(), PluginKind::Executable))
+        );
+        assert_eq!(
+            name("peniche-lint.v2.wasm", PluginOrigin::Workspace),
+            Some(("lint.v2".to_string(), PluginKind::Wasm))
+        );
+    }
+
+    #[test]
+    fn plugin_names_require_the_prefix_on_path() {
+        assert_eq!(
+            name(&executable("peniche-release"), PluginOrigin::Path),
+            Some(("release".to_string(), PluginKind::Executable))
+        );
+        assert_eq!(
+            name(&executable("release"), PluginOrigin::Workspace),
+            Some(("release".to_string(), PluginKind::Executable))
+        );
+        assert_eq!(name(&executable("cargo"), PluginOrigin::Path), None);
+        assert_eq!(name(&executable("peniche-"), PluginOrigin::Path), None);
+    }
+
+    #[test]
+    fn wasm_plugins_are_only_found_in_the_workspace() {
+        assert_eq!(name("peniche-lint.wasm", PluginOrigin::Path), None);
+        assert_eq!(
+            name("lint.wasm", PluginOrigin::Workspace),
+            Some(("lint".to_string(), PluginKind::Wasm))
+        );
+        assert_eq!(name(".gitkeep", PluginOrigin::Workspace), None);
+    }
+}
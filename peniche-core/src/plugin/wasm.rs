@@ -0,0 +1,137 @@
+use anyhow::Context as _;
+use std::path::Path;
+use wasmtime::{
+    Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+use wasmtime_wasi::pipe::MemoryInputPipe;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{I32Exit, WasiCtxBuilder};
+
+use crate::plugin::edit::ManifestEdit;
+use crate::plugin::{WORKSPACE_ENV, WORKSPACE_ROOT_ENV};
+use crate::workspace::Workspace;
+
+/// Module of the functions the host exposes to plugins.
+pub const HOST_MODULE: &str = "peniche";
+
+/// Edits are small JSON documents, larger ones are rejected before being copied.
+const MAX_EDIT_LEN: u32 = 1024 * 1024;
+
+/// Instructions a plugin may execute, around ten seconds of work, so a runaway module traps
+/// instead of hanging peniche.
+const MAX_FUEL: u64 = 10_000_000_000;
+
+/// Linear memory a plugin may allocate, growing past it fails.
+const MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Outcome of a WebAssembly plugin: its exit code and the manifest edits it proposed.
+#[derive(Debug)]
+pub struct WasmRun {
+    pub code: i32,
+    pub edits: Vec<ManifestEdit>,
+}
+
+struct PluginState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    edits: Vec<ManifestEdit>,
+}
+
+/// Runs a WASI (preview 1) module. The module gets no preopened directory nor network: the
+/// workspace model is written on its standard input as JSON, and manifest changes go through
+/// `peniche::propose_edit(ptr, len)`, which takes a JSON encoded `ManifestEdit` from the
+/// module memory and returns 0 when the edit is accepted, -1 otherwise. Modules run with a
+/// bounded amount of fuel and memory.
+pub fn run_module(
+    path: &Path,
+    name: &str,
+    args: &[String],
+    ws: Option<&Workspace>,
+    env: &[(&str, String)],
+) -> anyhow::Result<WasmRun> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::from_file(&engine, path)
+        .with_context(|| format!("Failed to load WebAssembly plugin {:?}", path))?;
+
+    let mut builder = WasiCtxBuilder::new();
+    let mut argv = vec![name.to_string()];
+    argv.extend(args.iter().cloned());
+    builder.args(&argv).inherit_stdout().inherit_stderr();
+    if let Some(ws) = ws {
+        builder
+            .stdin(MemoryInputPipe::new(serde_json::to_vec(ws)?))
+            .env(WORKSPACE_ENV, "-")
+            .env(WORKSPACE_ROOT_ENV, ws.path.to_string_lossy());
+    }
+    for (key, value) in env {
+        builder.env(key, value);
+    }
+
+    let mut linker: Linker<PluginState> = Linker::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |state: &mut PluginState| &mut state.wasi)?;
+    linker.func_wrap(HOST_MODULE, "propose_edit", propose_edit)?;
+
+    let mut store = Store::new(
+        &engine,
+        PluginState {
+            wasi: builder.build_p1(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY)
+                .instances(1)
+                .build(),
+            edits: vec![],
+        },
+    );
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(MAX_FUEL)?;
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .with_context(|| format!("Failed to instantiate WebAssembly plugin {:?}", path))?;
+    let start = instance
+        .get_typed_func::<(), ()>(&mut store, "_start")
+        .with_context(|| format!("WebAssembly plugin {:?} has no _start function", path))?;
+
+    let code = match start.call(&mut store, ()) {
+        Ok(()) => 0,
+        Err(err) => match (err.downcast_ref::<I32Exit>(), err.downcast_ref::<Trap>()) {
+            (Some(exit), _) => exit.0,
+            (None, Some(Trap::OutOfFuel)) => {
+                return Err(anyhow::anyhow!(
+                    "WebAssembly plugin {:?} ran out of fuel, it was stopped after {} instructions",
+                    path,
+                    MAX_FUEL
+                ))
+            }
+            (None, _) => return Err(err.context(format!("WebAssembly plugin {:?} trapped", path))),
+        },
+    };
+
+    Ok(WasmRun {
+        code,
+        edits: std::mem::take(&mut store.data_mut().edits),
+    })
+}
+
+fn propose_edit(mut caller: Caller<'_, PluginState>, ptr: u32, len: u32) -> i32 {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return -1;
+    };
+    if len > MAX_EDIT_LEN {
+        return -1;
+    }
+
+    let mut buffer = vec![0; len as usize];
+    if memory.read(&caller, ptr as usize, &mut buffer).is_err() {
+        return -1;
+    }
+
+    match serde_json::from_slice::<ManifestEdit>(&buffer) {
+        Ok(edit) if edit.check().is_ok() => {
+            caller.data_mut().edits.push(edit);
+            0
+        }
+        _ => -1,
+    }
+}
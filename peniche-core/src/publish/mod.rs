@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Context as _};
+use cargo::core::resolver::CliFeatures;
+use cargo::core::SourceId;
+use cargo::ops::{self, Packages, PublishOpts, RegistryOrIndex};
+use cargo::GlobalContext;
+use colored::Colorize as _;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::graph::WorkspaceGraph;
+use crate::krate::Krate;
+use crate::registry::{indexed_versions, registry_source_id};
+use crate::workspace::Workspace;
+use crate::{info_msg, success_msg};
+
+/// Delay between two lookups of a freshly published crate in the registry index.
+const VISIBILITY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// Alternative registry name, as configured in `.cargo/config.toml`
+    pub registry: Option<String>,
+    pub dry_run: bool,
+    pub allow_dirty: bool,
+    pub verify: bool,
+    /// How long to wait for a published crate to show up in the index
+    pub timeout: Duration,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            registry: None,
+            dry_run: false,
+            allow_dirty: false,
+            verify: true,
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishStatus {
+    Published,
+    /// Packaged with `--dry-run`, nothing was uploaded
+    DryRun,
+    AlreadyPublished,
+    /// `publish = false`, or the registry is not in the crate's `publish` list
+    NotPublishable,
+}
+
+/// Publishes `crates` in dependency order, skipping the versions already in the index and
+/// the crates that cannot be published. Each crate must be visible in the index before its
+/// dependents are published.
+pub fn publish_crates(
+    ws: &Workspace,
+    crates: &[&Krate],
+    options: &PublishOptions,
+) -> anyhow::Result<Vec<(String, PublishStatus)>> {
+    publish_crates_with(&GlobalContext::default()?, ws, crates, options)
+}
+
+/// [`publish_crates`] with the registries and cargo home of `gctx`.
+fn publish_crates_with(
+    gctx: &GlobalContext,
+    ws: &Workspace,
+    crates: &[&Krate],
+    options: &PublishOptions,
+) -> anyhow::Result<Vec<(String, PublishStatus)>> {
+    let cargo_ws = cargo::core::Workspace::new(&ws.manifest_path, gctx)?;
+    let source_id = registry_source_id(gctx, options.registry.as_deref())?;
+
+    let publish_lists: HashMap<String, Option<Vec<String>>> = cargo_ws
+        .members()
+        .map(|package| (package.name().to_string(), package.publish().clone()))
+        .collect();
+
+    let selected: HashSet<&str> = crates.iter().map(|krate| krate.name.as_str()).collect();
+    let order = WorkspaceGraph::from_workspace(ws).topological_order()?;
+
+    let mut statuses = vec![];
+    for name in order.iter().filter(|name| selected.contains(name.as_str())) {
+        let krate = &ws.crates[name];
+
+        let publishable = match publish_lists.get(name).cloned().flatten() {
+            None => true,
+            Some(registries) => match &options.registry {
+                Some(registry) => registries.contains(registry),
+                None => registries.iter().any(|registry| registry == "crates-io"),
+            },
+        };
+        if !publishable {
+            info_msg!("Skipping {}: not publishable to this registry", name);
+            statuses.push((name.clone(), PublishStatus::NotPublishable));
+            continue;
+        }
+
+        if is_in_index(gctx, source_id, name, &krate.version)? {
+            info_msg!("Skipping {} {}: already published", name, krate.version);
+            statuses.push((name.clone(), PublishStatus::AlreadyPublished));
+            continue;
+        }
+
+        info_msg!("Publishing {} {}", name.bold(), krate.version);
+        let opts = PublishOpts {
+            gctx,
+            token: None,
+            reg_or_index: options.registry.clone().map(RegistryOrIndex::Registry),
+            verify: options.verify,
+            allow_dirty: options.allow_dirty,
+            jobs: None,
+            keep_going: false,
+            to_publish: Packages::Packages(vec![name.clone()]),
+            targets: vec![],
+            dry_run: options.dry_run,
+            cli_features: CliFeatures::new_all(false),
+        };
+        ops::publish(&cargo_ws, &opts).with_context(|| format!("Failed to publish '{}'", name))?;
+
+        if options.dry_run {
+            success_msg!("Packaged {} {} (dry run)", name, krate.version);
+            statuses.push((name.clone(), PublishStatus::DryRun));
+            continue;
+        }
+
+        wait_until_visible(gctx, source_id, krate, options.timeout)?;
+        success_msg!("Published {} {}", name, krate.version);
+        statuses.push((name.clone(), PublishStatus::Published));
+    }
+
+    Ok(statuses)
+}
+
+/// Polls the index until `krate` shows up, so dependents can resolve it.
+fn wait_until_visible(
+    gctx: &GlobalContext,
+    source_id: SourceId,
+    krate: &Krate,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+
+    while !is_in_index(gctx, source_id, &krate.name, &krate.version)? {
+        if started.elapsed() > timeout {
+            return Err(anyhow!(
+                "{} {} was published but is still not in the index after {}s",
+                krate.name,
+                krate.version,
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(VISIBILITY_POLL_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Whether `name@version` exists in the registry index, yanked or not.
+fn is_in_index(
+    gctx: &GlobalContext,
+    source_id: SourceId,
+    name: &str,
+    version: &str,
+) -> anyhow::Result<bool> {
+    let requirement = format!("={}", version);
+    Ok(!indexed_versions(gctx, source_id, name, Some(&requirement))?.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Repository, Signature};
+    use std::path::Path;
+
+    /// Name of the alternative registry, configured on the command line of the test context so
+    /// the tests do not depend on a `.cargo/config.toml`.
+    const REGISTRY: &str = "peniche-test";
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Path of a crate in a registry index, relative to its root.
+    fn index_path(name: &str) -> String {
+        match name.len() {
+            1 => format!("1/{}", name),
+            2 => format!("2/{}", name),
+            3 => format!("3/{}/{}", &name[..1], name),
+            _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+        }
+    }
+
+    /// Adds `name@version` to the git index at `index` and commits it.
+    fn add_to_index(index: &Path, name: &str, version: &str) {
+        let entry = serde_json::json!({
+            "name": name,
+            "vers": version,
+            "deps": [],
+            "cksum": "0".repeat(64),
+            "features": {},
+            "yanked": false,
+        });
+        let path = index.join(index_path(name));
+        let mut content = std::fs::read_to_string(&path).unwrap_or_default();
+        content.push_str(&format!("{}\n", entry));
+        write(&path, &content);
+        commit_index(index, &format!("Add {} {}", name, version));
+    }
+
+    fn commit_index(index: &Path, message: &str) {
+        let repo = Repository::open(index).unwrap();
+        let mut tree = repo.index().unwrap();
+        tree.add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        tree.write().unwrap();
+        let tree = repo.find_tree(tree.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Peniche", "peniche@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    fn manifest(name: &str, extra: &str) -> String {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+             description = \"{}\"\nlicense = \"MIT\"\n{}",
+            name, name, extra
+        )
+    }
+
+    /// `app` depends on `base`, which is already in the index, and `internal` is private.
+    #[test]
+    fn publish_to_a_file_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("index");
+        Repository::init(&index).unwrap();
+        write(
+            &index.join("config.json"),
+            &serde_json::json!({
+                "dl": format!("file://{}/dl/{{crate}}-{{version}}.crate", dir.path().display()),
+                // Never contacted, publishing is a dry run
+                "api": "http://127.0.0.1:9",
+            })
+            .to_string(),
+        );
+        commit_index(&index, "Initial index");
+        add_to_index(&index, "base", "0.1.0");
+
+        let root = dir.path().join("ws");
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\", \"base\", \"internal\"]\nresolver = \"2\"\n",
+        );
+        write(&root.join("base/Cargo.toml"), &manifest("base", ""));
+        write(
+            &root.join("app/Cargo.toml"),
+            &manifest(
+                "app",
+                &format!(
+                    "\n[dependencies]\nbase = {{ path = \"../base\", version = \"0.1.0\", \
+                     registry = \"{}\" }}\n",
+                    REGISTRY
+                ),
+            ),
+        );
+        write(
+            &root.join("internal/Cargo.toml"),
+            &manifest("internal", "publish = false\n"),
+        );
+        for name in ["app", "base", "internal"] {
+            write(&root.join(name).join("src/lib.rs"), "");
+        }
+
+        // An explicit cargo home and registry, the process environment is shared with the
+        // other tests
+        let mut gctx = GlobalContext::new(
+            cargo::core::Shell::new(),
+            root.clone(),
+            dir.path().join("cargo-home"),
+        );
+        gctx.configure(
+            0,
+            true,
+            None,
+            false,
+            false,
+            false,
+            &None,
+            &[],
+            &[format!(
+                "registries.{}.index=\"file://{}\"",
+                REGISTRY,
+                index.display()
+            )],
+        )
+        .unwrap();
+
+        let ws = Workspace::from_path(&root.to_string_lossy()).unwrap();
+        let mut crates: Vec<&Krate> = ws.crates.values().collect();
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+        let options = PublishOptions {
+            registry: Some(REGISTRY.to_string()),
+            dry_run: true,
+            allow_dirty: true,
+            verify: false,
+            timeout: Duration::ZERO,
+        };
+
+        // `app` comes after `base` despite its name, `base` 0.1.0 is in the index already
+        let statuses = publish_crates_with(&gctx, &ws, &crates, &options).unwrap();
+        assert_eq!(
+            statuses,
+            [
+                ("base".to_string(), PublishStatus::AlreadyPublished),
+                ("app".to_string(), PublishStatus::DryRun),
+                ("internal".to_string(), PublishStatus::NotPublishable),
+            ]
+        );
+
+        let source_id = registry_source_id(&gctx, Some(REGISTRY)).unwrap();
+        let app = &ws.crates["app"];
+
+        let err = wait_until_visible(&gctx, source_id, app, Duration::ZERO).unwrap_err();
+        assert!(
+            err.to_string().contains("still not in the index"),
+            "{}",
+            err
+        );
+
+        // The index is refreshed on every lookup, so new versions show up
+        add_to_index(&index, "app", "0.1.0");
+        wait_until_visible(&gctx, source_id, app, Duration::ZERO).unwrap();
+        assert!(!is_in_index(&gctx, source_id, "app", "0.2.0").unwrap());
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::anyhow;
+use cargo::core::{Dependency, SourceId};
+use cargo::sources::source::{QueryKind, Source as _};
+use cargo::util::cache_lock::CacheLockMode;
+use cargo::GlobalContext;
+use semver::Version;
+use std::collections::HashSet;
+use std::task::Poll;
+
+/// Source of crates.io, or of an alternative registry configured in `.cargo/config.toml`.
+pub fn registry_source_id(
+    gctx: &GlobalContext,
+    registry: Option<&str>,
+) -> anyhow::Result<SourceId> {
+    match registry {
+        Some(registry) => Ok(SourceId::alt_registry(gctx, registry)?),
+        None => Ok(SourceId::crates_io_maybe_sparse_http(gctx)?),
+    }
+}
+
+/// A version of a crate found in a registry index.
+#[derive(Debug, Clone)]
+pub struct IndexedVersion {
+    pub version: Version,
+    pub yanked: bool,
+}
+
+/// Versions of `name` in the registry index matching `requirement`, any when unset. The
+/// index is refreshed first, so freshly published versions show up.
+pub fn indexed_versions(
+    gctx: &GlobalContext,
+    source_id: SourceId,
+    name: &str,
+    requirement: Option<&str>,
+) -> anyhow::Result<Vec<IndexedVersion>> {
+    let _lock = gctx.acquire_package_cache_lock(CacheLockMode::DownloadExclusive)?;
+    let mut source = source_id.load(gctx, &HashSet::new())?;
+    source.invalidate_cache();
+
+    let query = Dependency::parse(name, requirement, source_id)?;
+    let summaries = loop {
+        match source.query_vec(&query, QueryKind::Exact)? {
+            Poll::Ready(summaries) => break summaries,
+            Poll::Pending => source.block_until_ready()?,
+        }
+    };
+
+    Ok(summaries
+        .iter()
+        .map(|summary| IndexedVersion {
+            version: summary.as_summary().version().clone(),
+            yanked: summary.is_yanked(),
+        })
+        .collect())
+}
+
+/// Highest stable, non yanked version of `name`, or its highest prerelease when it has no
+/// stable version yet.
+pub fn latest_version(
+    gctx: &GlobalContext,
+    source_id: SourceId,
+    name: &str,
+) -> anyhow::Result<Option<Version>> {
+    let versions: Vec<Version> = indexed_versions(gctx, source_id, name, None)?
+        .into_iter()
+        .filter(|indexed| !indexed.yanked)
+        .map(|indexed| indexed.version)
+        .collect();
+
+    let stable = versions
+        .iter()
+        .filter(|version| version.pre.is_empty())
+        .max();
+    Ok(stable.or(versions.iter().max()).cloned())
+}
+
+/// [`latest_version`] of `name` in crates.io or in an alternative registry.
+pub fn find_latest_version(registry: Option<&str>, name: &str) -> anyhow::Result<Version> {
+    let gctx = GlobalContext::default()?;
+    let source_id = registry_source_id(&gctx, registry)?;
+
+    latest_version(&gctx, source_id, name)?
+        .ok_or_else(|| anyhow!("Crate '{}' not found in the registry", name))
+}
@@ -0,0 +1,422 @@
+use anyhow::{anyhow, Context as _};
+use git2::{DiffOptions, Repository, Sort};
+use semver::Version;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::krate::{Krate, KrateSource};
+use crate::release::Bump;
+
+/// Tag name used for a crate release when none is configured.
+pub const DEFAULT_TAG_TEMPLATE: &str = "{name}-v{version}";
+
+pub fn tag_name(template: &str, name: &str, version: &Version) -> String {
+    template
+        .replace("{name}", name)
+        .replace("{version}", &version.to_string())
+}
+
+/// A commit message following the Conventional Commits specification, or a plain message
+/// when it does not (`kind` is then empty).
+#[derive(Debug, Clone, Serialize)]
+pub struct ConventionalCommit {
+    pub id: String,
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl ConventionalCommit {
+    pub fn parse(id: &str, message: &str) -> Self {
+        let header = message.lines().next().unwrap_or_default().trim();
+        let breaking_footer = message.lines().skip(1).any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+
+        let plain = ConventionalCommit {
+            id: id.to_string(),
+            kind: String::new(),
+            scope: None,
+            breaking: breaking_footer,
+            description: header.to_string(),
+        };
+
+        let Some((prefix, description)) = header.split_once(':') else {
+            return plain;
+        };
+
+        let (prefix, bang) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+
+        let (kind, scope) = match prefix.split_once('(') {
+            Some((kind, scope)) => match scope.strip_suffix(')') {
+                Some(scope) => (kind, Some(scope.to_string())),
+                None => return plain,
+            },
+            None => (prefix, None),
+        };
+
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return plain;
+        }
+
+        ConventionalCommit {
+            id: id.to_string(),
+            kind: kind.to_lowercase(),
+            scope,
+            breaking: bang || breaking_footer,
+            description: description.trim().to_string(),
+        }
+    }
+
+    /// Bump this commit calls for. Below 1.0.0 every level shifts down, as cargo treats the
+    /// minor version as the breaking one.
+    pub fn bump(&self, version: &Version) -> Option<Bump> {
+        let bump = if self.breaking {
+            Bump::Major
+        } else {
+            match self.kind.as_str() {
+                "feat" => Bump::Minor,
+                "fix" | "perf" | "revert" => Bump::Patch,
+                // Non conventional messages may hide anything, release them as a fix
+                "" => Bump::Patch,
+                _ => return None,
+            }
+        };
+
+        if version.major == 0 {
+            match bump {
+                Bump::Major => Some(Bump::Minor),
+                _ => Some(Bump::Patch),
+            }
+        } else {
+            Some(bump)
+        }
+    }
+}
+
+/// Highest bump called for by `commits`, if any.
+pub fn suggested_bump(commits: &[ConventionalCommit], version: &Version) -> Option<Bump> {
+    commits
+        .iter()
+        .filter_map(|commit| commit.bump(version))
+        .max()
+}
+
+/// Commits touching the crate directory since its release tag (the whole history when the
+/// tag does not exist), newest first.
+pub fn commits_since_release(
+    repo: &Repository,
+    krate: &Krate,
+    tag: &str,
+) -> anyhow::Result<Vec<ConventionalCommit>> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("Cannot read history of a bare repository"))?;
+    let KrateSource::Path(path) = &krate.path else {
+        return Err(anyhow!("Crate '{}' is not a workspace member", krate.name));
+    };
+    let pathspec = relative_pathspec(workdir, path)?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    if revwalk.push_head().is_err() {
+        // Unborn branch, no history yet
+        return Ok(vec![]);
+    }
+    if let Ok(tagged) = repo
+        .revparse_single(&format!("refs/tags/{}", tag))
+        .and_then(|object| object.peel_to_commit())
+    {
+        revwalk.hide(tagged.id())?;
+    }
+
+    let mut commits = vec![];
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+
+        let mut opts = DiffOptions::new();
+        if let Some(pathspec) = &pathspec {
+            opts.pathspec(pathspec);
+        }
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))?;
+        if diff.deltas().len() == 0 {
+            continue;
+        }
+
+        let id = commit.as_object().short_id()?;
+        commits.push(ConventionalCommit::parse(
+            id.as_str().unwrap_or_default(),
+            commit.message().unwrap_or_default(),
+        ));
+    }
+
+    Ok(commits)
+}
+
+/// Path of `path` inside the repository, `None` when it is the repository root.
+fn relative_pathspec(workdir: &Path, path: &Path) -> anyhow::Result<Option<String>> {
+    let workdir = workdir.canonicalize().unwrap_or(workdir.to_path_buf());
+    let path = path.canonicalize().unwrap_or(path.to_path_buf());
+    let relative = path
+        .strip_prefix(&workdir)
+        .with_context(|| format!("{:?} is outside of the git repository", path))?;
+
+    if relative.as_os_str().is_empty() {
+        return Ok(None);
+    }
+
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Ok(Some(components.join("/")))
+}
+
+/// Markdown section describing a release, hand written notes first.
+pub fn changelog_section(
+    version: &Version,
+    commits: &[ConventionalCommit],
+    notes: &[String],
+) -> String {
+    let groups: [(&str, fn(&ConventionalCommit) -> bool); 4] = [
+        ("⚠ Breaking changes", |c| c.breaking),
+        ("Features", |c| !c.breaking && c.kind == "feat"),
+        ("Bug fixes", |c| !c.breaking && c.kind == "fix"),
+        ("Other changes", |c| {
+            !c.breaking && c.kind != "feat" && c.kind != "fix"
+        }),
+    ];
+
+    let mut section = format!("## [{}] - {}\n", version, today());
+    if !notes.is_empty() {
+        let entries: Vec<String> = notes
+            .iter()
+            .map(|note| format!("- {}", note.trim().replace('\n', "\n  ")))
+            .collect();
+        section.push_str(&format!("\n### Changes\n\n{}\n", entries.join("\n")));
+    }
+    for (title, belongs) in groups {
+        let entries: Vec<String> = commits
+            .iter()
+            .filter(|commit| belongs(commit))
+            .map(|commit| match &commit.scope {
+                Some(scope) => format!("- **{}:** {} ({})", scope, commit.description, commit.id),
+                None => format!("- {} ({})", commit.description, commit.id),
+            })
+            .collect();
+
+        if !entries.is_empty() {
+            section.push_str(&format!("\n### {}\n\n{}\n", title, entries.join("\n")));
+        }
+    }
+
+    section
+}
+
+/// Inserts `section` at the top of `CHANGELOG.md` in `dir`, below its title.
+pub fn prepend_changelog(dir: &Path, section: &str) -> anyhow::Result<()> {
+    let path = dir.join("CHANGELOG.md");
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {:?}", path)),
+    };
+
+    let (title, rest) = match existing.strip_prefix("# ") {
+        Some(_) => existing.split_once('\n').unwrap_or((existing.as_str(), "")),
+        None => ("# Changelog", existing.as_str()),
+    };
+
+    let content = format!(
+        "{}\n\n{}\n{}",
+        title,
+        section,
+        rest.trim_start_matches('\n')
+    );
+    std::fs::write(&path, content.trim_end().to_string() + "\n")
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Current UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default() as i64;
+
+    // Civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Oid, RepositoryInitOptions};
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    /// Writes `content` to `path` and commits it on top of HEAD.
+    fn commit(repo: &Repository, path: &str, content: &str, message: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        let full_path = workdir.join(path);
+        std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        std::fs::write(&full_path, content).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = repo.signature().unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_conventional_commits() {
+        let commit = ConventionalCommit::parse("abc1234", "feat(parser): support tables\n\nBody");
+        assert_eq!(commit.kind, "feat");
+        assert_eq!(commit.scope.as_deref(), Some("parser"));
+        assert!(!commit.breaking);
+        assert_eq!(commit.description, "support tables");
+
+        let commit = ConventionalCommit::parse("abc1234", "Fix!: drop the old API");
+        assert_eq!(commit.kind, "fix");
+        assert!(commit.breaking);
+
+        let commit = ConventionalCommit::parse(
+            "abc1234",
+            "refactor: rename the config\n\nBREAKING CHANGE: `cmd` is now `commands`",
+        );
+        assert_eq!(commit.kind, "refactor");
+        assert!(commit.breaking);
+    }
+
+    #[test]
+    fn parse_plain_messages() {
+        for message in [
+            "Update README",
+            "fix typo: in docs",
+            "feat(parser: missing paren",
+        ] {
+            let commit = ConventionalCommit::parse("abc1234", message);
+            assert_eq!(commit.kind, "", "{}", message);
+            assert_eq!(commit.description, message);
+        }
+    }
+
+    #[test]
+    fn bump_from_commit_kind() {
+        let bump = |message: &str, current: &str| {
+            ConventionalCommit::parse("abc1234", message).bump(&version(current))
+        };
+
+        assert_eq!(bump("feat: add", "1.2.3"), Some(Bump::Minor));
+        assert_eq!(bump("fix: repair", "1.2.3"), Some(Bump::Patch));
+        assert_eq!(bump("perf: faster", "1.2.3"), Some(Bump::Patch));
+        assert_eq!(bump("feat!: break", "1.2.3"), Some(Bump::Major));
+        assert_eq!(bump("Update README", "1.2.3"), Some(Bump::Patch));
+        assert_eq!(bump("chore: tidy", "1.2.3"), None);
+        assert_eq!(bump("docs: explain", "1.2.3"), None);
+
+        // Below 1.0.0 the minor version is the breaking one
+        assert_eq!(bump("feat!: break", "0.4.1"), Some(Bump::Minor));
+        assert_eq!(bump("feat: add", "0.4.1"), Some(Bump::Patch));
+    }
+
+    #[test]
+    fn suggested_bump_is_the_highest() {
+        let commits: Vec<ConventionalCommit> = ["chore: tidy", "fix: repair", "feat: add"]
+            .into_iter()
+            .map(|message| ConventionalCommit::parse("abc1234", message))
+            .collect();
+
+        assert_eq!(
+            suggested_bump(&commits, &version("1.0.0")),
+            Some(Bump::Minor)
+        );
+        assert_eq!(suggested_bump(&commits[..1], &version("1.0.0")), None);
+    }
+
+    #[test]
+    fn commits_since_release_only_lists_the_crate_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = RepositoryInitOptions::new();
+        opts.initial_head("main");
+        let repo = Repository::init_opts(dir.path(), &opts).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Peniche").unwrap();
+        config.set_str("user.email", "peniche@example.com").unwrap();
+
+        let core_dir = dir.path().join("crates/core");
+        let core = Krate::new(
+            "core".to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(core_dir),
+        );
+
+        assert!(commits_since_release(&repo, &core, "core-v0.1.0")
+            .unwrap()
+            .is_empty());
+
+        let released = commit(
+            &repo,
+            "crates/core/src/lib.rs",
+            "",
+            "feat: initial release",
+        );
+        let released = repo.find_object(released, None).unwrap();
+        repo.tag_lightweight("core-v0.1.0", &released, false)
+            .unwrap();
+        commit(
+            &repo,
+            "crates/core/src/lib.rs",
+            "// fixed",
+            "fix: repair parsing",
+        );
+        commit(
+            &repo,
+            "crates/cli/src/main.rs",
+            "fn main() {}",
+            "feat: add the cli",
+        );
+        commit(
+            &repo,
+            "crates/core/src/lib.rs",
+            "// faster",
+            "perf(core): cache lookups",
+        );
+
+        let commits = commits_since_release(&repo, &core, "core-v0.1.0").unwrap();
+        let descriptions: Vec<&str> = commits.iter().map(|c| c.description.as_str()).collect();
+        assert_eq!(descriptions, ["cache lookups", "repair parsing"]);
+
+        // Without the tag, the whole history of the crate is listed
+        let commits = commits_since_release(&repo, &core, "core-v9.9.9").unwrap();
+        assert_eq!(commits.len(), 3);
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Context as _};
+use rand::seq::SliceRandom as _;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::release::Bump;
+use crate::workspace::Workspace;
+
+/// Directory of the pending changesets, relative to the workspace root.
+pub const CHANGESET_DIR: &str = ".peniche/changesets";
+
+const ADJECTIVES: &[&str] = &[
+    "brave", "calm", "eager", "fuzzy", "gentle", "happy", "lazy", "lucky", "mighty", "quiet",
+    "shiny", "swift", "tidy", "witty", "young",
+];
+const NOUNS: &[&str] = &[
+    "anchor",
+    "buoy",
+    "canal",
+    "dock",
+    "ferry",
+    "harbor",
+    "keel",
+    "lighthouse",
+    "mast",
+    "oar",
+    "pier",
+    "rudder",
+    "sail",
+    "tide",
+    "wharf",
+];
+
+/// A release intent: the crates a change should be released in, with a summary for their
+/// changelogs. Stored as markdown with a front matter listing the bumps:
+///
+/// ```markdown
+/// ---
+/// "core": minor
+/// "cli": patch
+/// ---
+///
+/// Add support for sparse registries.
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct Changeset {
+    /// File stem under `.peniche/changesets/`
+    pub id: String,
+    pub bumps: BTreeMap<String, Bump>,
+    pub summary: String,
+}
+
+impl Changeset {
+    pub fn new(bumps: BTreeMap<String, Bump>, summary: String) -> Self {
+        Self {
+            id: String::new(),
+            bumps,
+            summary,
+        }
+    }
+
+    pub fn parse(id: &str, content: &str) -> anyhow::Result<Self> {
+        let content = content.trim_start();
+        let rest = content
+            .strip_prefix("---")
+            .ok_or_else(|| anyhow!("Missing front matter"))?;
+        let (front_matter, summary) = rest
+            .split_once("\n---")
+            .ok_or_else(|| anyhow!("Unterminated front matter"))?;
+
+        let mut bumps = BTreeMap::new();
+        for line in front_matter
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+        {
+            let (name, bump) = line
+                .rsplit_once(':')
+                .ok_or_else(|| anyhow!("Invalid line '{}', expected \"crate\": bump", line))?;
+            let name = name.trim().trim_matches('"').trim_matches('\'');
+            bumps.insert(name.to_string(), bump.trim().parse()?);
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            bumps,
+            summary: summary.trim().to_string(),
+        })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let bumps: Vec<String> = self
+            .bumps
+            .iter()
+            .map(|(name, bump)| format!("\"{}\": {}", name, bump))
+            .collect();
+
+        format!(
+            "---\n{}\n---\n\n{}\n",
+            bumps.join("\n"),
+            self.summary.trim()
+        )
+    }
+
+    pub fn path(&self, ws: &Workspace) -> PathBuf {
+        ws.path.join(CHANGESET_DIR).join(format!("{}.md", self.id))
+    }
+
+    /// Writes the changeset under a new random name, returning its path.
+    pub fn write(&mut self, ws: &Workspace) -> anyhow::Result<PathBuf> {
+        for name in self.bumps.keys() {
+            if !ws.crates.contains_key(name) {
+                return Err(anyhow!("Crate '{}' not found", name));
+            }
+        }
+
+        let dir = ws.path.join(CHANGESET_DIR);
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        let mut rng = rand::thread_rng();
+        loop {
+            let adjective = ADJECTIVES.choose(&mut rng).expect("non empty");
+            let noun = NOUNS.choose(&mut rng).expect("non empty");
+            self.id = format!("{}-{}-{:03}", adjective, noun, rand::random::<u16>() % 1000);
+            if !self.path(ws).exists() {
+                break;
+            }
+        }
+
+        let path = self.path(ws);
+        std::fs::write(&path, self.to_markdown())
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        Ok(path)
+    }
+}
+
+/// Changesets waiting to be released, sorted by name.
+pub fn pending_changesets(ws: &Workspace) -> anyhow::Result<Vec<Changeset>> {
+    let dir = ws.path.join(CHANGESET_DIR);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| read_changeset(path)).collect()
+}
+
+/// Writes back the changesets a release consumed: those left without entries are deleted,
+/// the others are rewritten with their remaining crates. Returns their paths.
+pub fn update_changesets(ws: &Workspace, changesets: &[Changeset]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for changeset in changesets {
+        let path = changeset.path(ws);
+        if changeset.bumps.is_empty() {
+            std::fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+        } else {
+            std::fs::write(&path, changeset.to_markdown())
+                .with_context(|| format!("Failed to write {:?}", path))?;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn read_changeset(path: &Path) -> anyhow::Result<Changeset> {
+    let id = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    Changeset::parse(&id, &content).with_context(|| format!("Invalid changeset {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::krate::{Krate, KrateSource};
+
+    fn bumps(entries: &[(&str, Bump)]) -> BTreeMap<String, Bump> {
+        entries
+            .iter()
+            .map(|(name, bump)| (name.to_string(), *bump))
+            .collect()
+    }
+
+    #[test]
+    fn parse_front_matter_and_summary() {
+        let changeset = Changeset::parse(
+            "brave-buoy-042",
+            "---\n\"core\": minor\n'cli': patch\napi: prerelease\n---\n\nAdd sparse registries.\n",
+        )
+        .unwrap();
+
+        assert_eq!(changeset.id, "brave-buoy-042");
+        assert_eq!(
+            changeset.bumps,
+            bumps(&[
+                ("api", Bump::Prerelease),
+                ("cli", Bump::Patch),
+                ("core", Bump::Minor)
+            ])
+        );
+        assert_eq!(changeset.summary, "Add sparse registries.");
+    }
+
+    #[test]
+    fn parse_invalid_changesets() {
+        for content in [
+            "Add sparse registries.",
+            "---\n\"core\": minor\n",
+            "---\n\"core\"\n---\n",
+            "---\n\"core\": huge\n---\n",
+        ] {
+            assert!(Changeset::parse("id", content).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ws = Workspace::new(dir.path().to_path_buf());
+        for name in ["core", "cli"] {
+            let krate = Krate::new(
+                name.to_string(),
+                "0.1.0".to_string(),
+                KrateSource::Path(dir.path().join(name)),
+            );
+            ws.crates.insert(name.to_string(), krate);
+        }
+
+        let mut changeset = Changeset::new(
+            bumps(&[("core", Bump::Major), ("cli", Bump::Patch)]),
+            "Rename the config.\n\nThe `cmd` section is now `commands`.".to_string(),
+        );
+        let path = changeset.write(&ws).unwrap();
+        assert_eq!(
+            path,
+            dir.path()
+                .join(CHANGESET_DIR)
+                .join(format!("{}.md", changeset.id))
+        );
+
+        let pending = pending_changesets(&ws).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, changeset.id);
+        assert_eq!(pending[0].bumps, changeset.bumps);
+        assert_eq!(pending[0].summary, changeset.summary);
+
+        // The release of `core` leaves the `cli` entry for a later one
+        let mut partial = pending[0].clone();
+        partial.bumps.remove("core");
+        assert_eq!(update_changesets(&ws, &[partial]).unwrap(), [path.clone()]);
+        let pending = pending_changesets(&ws).unwrap();
+        assert_eq!(pending[0].bumps, bumps(&[("cli", Bump::Patch)]));
+        assert_eq!(pending[0].summary, changeset.summary);
+
+        let mut consumed = pending[0].clone();
+        consumed.bumps.clear();
+        assert_eq!(update_changesets(&ws, &[consumed]).unwrap(), [path]);
+        assert!(pending_changesets(&ws).unwrap().is_empty());
+
+        // A changeset written without entries is deleted with the next release
+        let empty = dir.path().join(CHANGESET_DIR).join("empty.md");
+        std::fs::write(&empty, "---
+---
+
+Nothing to release.
+").unwrap();
+        let pending = pending_changesets(&ws).unwrap();
+        assert!(pending[0].bumps.is_empty());
+        assert_eq!(update_changesets(&ws, &pending).unwrap(), [empty.clone()]);
+        assert!(!empty.exists());
+    }
+
+    #[test]
+    fn write_rejects_unknown_crates() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = Workspace::new(dir.path().to_path_buf());
+        let mut changeset = Changeset::new(bumps(&[("core", Bump::Minor)]), "Fix".to_string());
+
+        assert!(changeset.write(&ws).is_err());
+        assert!(!dir.path().join(CHANGESET_DIR).exists());
+    }
+}
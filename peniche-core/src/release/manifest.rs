@@ -0,0 +1,259 @@
+use anyhow::{anyhow, Context as _};
+use cargo_util::paths::write_atomic;
+use colored::Colorize as _;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+/// Dependency tables of a manifest, outside of `[target.'cfg(..)']` sections.
+pub const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Manifests loaded for edition, written back only when their content changed.
+#[derive(Debug, Default)]
+pub struct ManifestSet {
+    documents: BTreeMap<PathBuf, (String, DocumentMut)>,
+}
+
+impl ManifestSet {
+    pub fn get_mut(&mut self, path: &Path) -> anyhow::Result<&mut DocumentMut> {
+        if !self.documents.contains_key(path) {
+            let original = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read manifest at {:?}", path))?;
+            let document = original
+                .parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse manifest at {:?}", path))?;
+            self.documents
+                .insert(path.to_path_buf(), (original, document));
+        }
+
+        Ok(&mut self
+            .documents
+            .get_mut(path)
+            .expect("manifest just loaded")
+            .1)
+    }
+
+    /// Manifests whose content changed, with their original and updated content.
+    pub fn changes(&self) -> impl Iterator<Item = (&PathBuf, &str, String)> {
+        self.documents
+            .iter()
+            .map(|(path, (original, document))| (path, original.as_str(), document.to_string()))
+            .filter(|(_, original, updated)| original != updated)
+    }
+
+    pub fn changed_paths(&self) -> Vec<PathBuf> {
+        self.changes().map(|(path, _, _)| path.clone()).collect()
+    }
+
+    pub fn write(&self) -> anyhow::Result<()> {
+        for (path, _, updated) in self.changes() {
+            write_atomic(path, updated.as_bytes())
+                .with_context(|| format!("Failed to write manifest at {:?}", path))?;
+        }
+        Ok(())
+    }
+
+    /// Prints a line based diff of every pending change.
+    pub fn print_diff(&self) {
+        for (path, original, updated) in self.changes() {
+            println!("{}", format!("--- {}", path.display()).bold());
+            println!("{}", format!("+++ {}", path.display()).bold());
+
+            let before: Vec<&str> = original.lines().collect();
+            let after: Vec<&str> = updated.lines().collect();
+
+            if before.len() != after.len() {
+                // Only happens when lines were added, show the whole file
+                before
+                    .iter()
+                    .for_each(|line| println!("{}", format!("-{}", line).red()));
+                after
+                    .iter()
+                    .for_each(|line| println!("{}", format!("+{}", line).green()));
+                continue;
+            }
+
+            for (number, (old, new)) in before.iter().zip(&after).enumerate() {
+                if old != new {
+                    println!("{}", format!("@@ line {} @@", number + 1).cyan());
+                    println!("{}", format!("-{}", old).red());
+                    println!("{}", format!("+{}", new).green());
+                }
+            }
+        }
+    }
+}
+
+/// Replaces a string value, keeping the surrounding comments and whitespace.
+pub fn set_string(item: &mut Item, value: &str) {
+    match item.as_value_mut() {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = Value::from(value);
+            *existing.decor_mut() = decor;
+        }
+        None => *item = toml_edit::value(value),
+    }
+}
+
+/// Whether `package.<field>` is declared as `{ workspace = true }`.
+pub fn is_inherited(document: &DocumentMut, field: &str) -> bool {
+    document
+        .get("package")
+        .and_then(|package| package.get(field))
+        .and_then(|value| value.get("workspace"))
+        .and_then(Item::as_bool)
+        == Some(true)
+}
+
+/// Sets `package.version` of a member manifest.
+pub fn set_package_version(document: &mut DocumentMut, version: &str) -> anyhow::Result<()> {
+    let package = document
+        .get_mut("package")
+        .ok_or_else(|| anyhow!("No [package] section in manifest"))?;
+    set_string(&mut package["version"], version);
+    Ok(())
+}
+
+/// Sets `workspace.package.version` of the root manifest.
+pub fn set_workspace_version(document: &mut DocumentMut, version: &str) -> anyhow::Result<()> {
+    let package = document
+        .get_mut("workspace")
+        .and_then(|workspace| workspace.get_mut("package"))
+        .ok_or_else(|| anyhow!("No [workspace.package] section in the root manifest"))?;
+    set_string(&mut package["version"], version);
+    Ok(())
+}
+
+/// Every dependency table of a manifest, `[target.'cfg(..)'.*]` and
+/// `[workspace.dependencies]` included.
+pub fn dependency_tables_mut(document: &mut DocumentMut) -> Vec<&mut dyn TableLike> {
+    collect_dependency_tables(document, true)
+}
+
+/// Dependency tables of the package of a manifest, `[target.'cfg(..)'.*]` included.
+pub fn package_dependency_tables_mut(document: &mut DocumentMut) -> Vec<&mut dyn TableLike> {
+    collect_dependency_tables(document, false)
+}
+
+fn collect_dependency_tables(
+    document: &mut DocumentMut,
+    include_workspace: bool,
+) -> Vec<&mut dyn TableLike> {
+    let mut tables = vec![];
+
+    for (key, item) in document.iter_mut() {
+        match key.get() {
+            "target" => {
+                let Some(targets) = item.as_table_like_mut() else {
+                    continue;
+                };
+                for (_, target) in targets.iter_mut() {
+                    let Some(target) = target.as_table_like_mut() else {
+                        continue;
+                    };
+                    for (key, table) in target.iter_mut() {
+                        if DEPENDENCY_TABLES.contains(&key.get()) {
+                            tables.extend(table.as_table_like_mut());
+                        }
+                    }
+                }
+            }
+            "workspace" if include_workspace => {
+                let Some(workspace) = item.as_table_like_mut() else {
+                    continue;
+                };
+                if let Some(dependencies) = workspace.get_mut("dependencies") {
+                    tables.extend(dependencies.as_table_like_mut());
+                }
+            }
+            key if DEPENDENCY_TABLES.contains(&key) => {
+                tables.extend(item.as_table_like_mut());
+            }
+            _ => {}
+        }
+    }
+
+    tables
+}
+
+/// Name of the crate behind a dependency entry, following `package = "..."` renames.
+pub fn dependency_name<'a>(key: &'a str, entry: &'a Item) -> &'a str {
+    entry.get("package").and_then(Item::as_str).unwrap_or(key)
+}
+
+/// Requirement on `version` keeping the operator of `requirement`, when it is a single `=`,
+/// `^`, `~` or `>=` comparator or a bare version. Ranges, wildcards and upper bounds cannot
+/// be moved without changing their meaning, `None` is returned for them.
+pub fn updated_requirement(requirement: &str, version: &str) -> Option<String> {
+    let requirement = requirement.trim();
+    let operator: String = requirement
+        .chars()
+        .take_while(|c| matches!(c, '=' | '^' | '~' | '>' | '<'))
+        .collect();
+    let current = requirement[operator.len()..].trim();
+
+    let movable = matches!(operator.as_str(), "" | "=" | "^" | "~" | ">=")
+        && !current.is_empty()
+        && !current.contains(',')
+        && !current
+            .split('.')
+            .any(|part| matches!(part, "*" | "x" | "X"));
+
+    movable.then(|| format!("{}{}", operator, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updated_requirement_keeps_the_operator() {
+        assert_eq!(
+            updated_requirement("0.1.0", "0.2.0").as_deref(),
+            Some("0.2.0")
+        );
+        assert_eq!(
+            updated_requirement("^1.2", "1.3.0").as_deref(),
+            Some("^1.3.0")
+        );
+        assert_eq!(
+            updated_requirement("=1.2.3", "1.2.4").as_deref(),
+            Some("=1.2.4")
+        );
+        assert_eq!(
+            updated_requirement("~1.2", "1.3.0").as_deref(),
+            Some("~1.3.0")
+        );
+        assert_eq!(
+            updated_requirement(">=1.2", "2.0.0").as_deref(),
+            Some(">=2.0.0")
+        );
+        assert_eq!(
+            updated_requirement(" ^ 1.2 ", "1.3.0").as_deref(),
+            Some("^1.3.0")
+        );
+    }
+
+    #[test]
+    fn updated_requirement_refuses_ranges() {
+        for requirement in [
+            ">=1.2, <2",
+            "<2",
+            "<=1.9",
+            ">1.2",
+            "*",
+            "1.*",
+            "1.x",
+            "",
+            "^",
+        ] {
+            assert_eq!(
+                updated_requirement(requirement, "1.3.0"),
+                None,
+                "{}",
+                requirement
+            );
+        }
+    }
+}
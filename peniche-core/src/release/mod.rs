@@ -0,0 +1,953 @@
+use anyhow::{anyhow, Context as _};
+use cargo::GlobalContext;
+use colored::Colorize as _;
+use git2::{Oid, Repository};
+use semver::{Prerelease, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml_edit::DocumentMut;
+
+use crate::git;
+use crate::graph::WorkspaceGraph;
+use crate::krate::{DependencyKind, Krate, KrateSource};
+use crate::workspace::Workspace;
+
+pub mod changelog;
+pub mod changeset;
+pub mod manifest;
+
+use changelog::{
+    changelog_section, commits_since_release, prepend_changelog, suggested_bump, tag_name,
+    ConventionalCommit, DEFAULT_TAG_TEMPLATE,
+};
+use changeset::Changeset;
+use manifest::{
+    dependency_name, dependency_tables_mut, is_inherited, set_package_version, set_string,
+    set_workspace_version, updated_requirement, ManifestSet,
+};
+
+/// Semver component to increment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+    Prerelease,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl FromStr for Bump {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "major" => Ok(Bump::Major),
+            "minor" => Ok(Bump::Minor),
+            "patch" => Ok(Bump::Patch),
+            "prerelease" => Ok(Bump::Prerelease),
+            _ => Err(anyhow!(
+                "Unknown bump '{}' (expected major, minor, patch or prerelease)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Bump::Prerelease => "prerelease",
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Bump {
+    /// Next version after `version`. A prerelease is promoted to its release when the bump
+    /// does not go past it (`1.0.0-rc.1` + major = `1.0.0`), `prerelease` increments the last
+    /// numeric identifier or starts a `<preid>.0` prerelease of the next patch.
+    pub fn apply(&self, version: &Version, preid: &str) -> Version {
+        let mut next = version.clone();
+        let is_prerelease = !version.pre.is_empty();
+        next.build = semver::BuildMetadata::EMPTY;
+
+        match self {
+            Bump::Major => {
+                if !(is_prerelease && version.minor == 0 && version.patch == 0) {
+                    next.major += 1;
+                }
+                next.minor = 0;
+                next.patch = 0;
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Minor => {
+                if !(is_prerelease && version.patch == 0) {
+                    next.minor += 1;
+                }
+                next.patch = 0;
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Patch => {
+                if !is_prerelease {
+                    next.patch += 1;
+                }
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Prerelease => {
+                let pre = if is_prerelease {
+                    increment_prerelease(version.pre.as_str())
+                } else {
+                    next.patch += 1;
+                    format!("{}.0", preid)
+                };
+                next.pre = Prerelease::new(&pre).unwrap_or(Prerelease::EMPTY);
+            }
+        }
+
+        next
+    }
+}
+
+fn increment_prerelease(pre: &str) -> String {
+    let mut identifiers: Vec<String> = pre.split('.').map(String::from).collect();
+    match identifiers.last().and_then(|last| last.parse::<u64>().ok()) {
+        Some(number) => *identifiers.last_mut().expect("non empty") = (number + 1).to_string(),
+        None => identifiers.push("1".to_string()),
+    }
+    identifiers.join(".")
+}
+
+/// Version requested on the command line: a bump or an exact version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSpec {
+    Bump(Bump),
+    Exact(Version),
+}
+
+impl FromStr for VersionSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<Bump>() {
+            Ok(bump) => Ok(VersionSpec::Bump(bump)),
+            Err(_) => Version::parse(s.trim_start_matches('v'))
+                .map(VersionSpec::Exact)
+                .map_err(|_| {
+                    anyhow!(
+                        "Invalid version '{}' (expected major, minor, patch, prerelease or a semver version)",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl VersionSpec {
+    pub fn next(&self, version: &Version, preid: &str) -> Version {
+        match self {
+            VersionSpec::Bump(bump) => bump.apply(version, preid),
+            VersionSpec::Exact(version) => version.clone(),
+        }
+    }
+}
+
+/// How the versions of the workspace members relate to each other.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VersioningMode {
+    /// Every crate is versioned on its own, except for the configured groups
+    #[default]
+    Independent,
+    /// All the members share a single version
+    Fixed,
+}
+
+/// Which dependents of a released crate get released as well.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Propagation {
+    /// Every crate depending on it, outside of dev-dependencies
+    #[default]
+    Always,
+    /// Only the crates whose requirement does not accept the new version
+    Breaking,
+    Never,
+}
+
+/// Options of a `peniche release` invocation.
+#[derive(Debug, Clone)]
+pub struct ReleaseOptions {
+    /// Requested version, inferred from the commit history when missing
+    pub version: Option<VersionSpec>,
+    pub preid: String,
+    /// Name of the release tags, with `{name}` and `{version}` placeholders
+    pub tag_template: String,
+    pub mode: VersioningMode,
+    /// Crates released together with a shared version, by group name
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// Pending changesets, used instead of the commit history when no version is requested
+    pub changesets: Vec<Changeset>,
+    /// When the dependents of a released crate are released too
+    pub propagation: Propagation,
+    /// Minimum bump of the dependents released because of a dependency
+    pub dependent_bump: Bump,
+}
+
+impl Default for ReleaseOptions {
+    fn default() -> Self {
+        Self {
+            version: None,
+            preid: "alpha".to_string(),
+            tag_template: DEFAULT_TAG_TEMPLATE.to_string(),
+            mode: VersioningMode::default(),
+            groups: BTreeMap::new(),
+            changesets: vec![],
+            propagation: Propagation::default(),
+            dependent_bump: Bump::Patch,
+        }
+    }
+}
+
+/// Crates sharing a version, with the reason given to the members released only because
+/// another member changed.
+struct LockstepSet<'a> {
+    reason: String,
+    members: Vec<&'a str>,
+}
+
+fn lockstep_sets<'a>(
+    ws: &'a Workspace,
+    options: &'a ReleaseOptions,
+) -> anyhow::Result<Vec<LockstepSet<'a>>> {
+    if options.mode == VersioningMode::Fixed {
+        return Ok(vec![LockstepSet {
+            reason: "fixed version".to_string(),
+            members: ws.crates.keys().map(String::as_str).collect(),
+        }]);
+    }
+
+    let mut grouped: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut sets = vec![];
+    for (group, members) in &options.groups {
+        for member in members {
+            if !ws.crates.contains_key(member) {
+                return Err(anyhow!(
+                    "Release group '{}' lists unknown crate '{}'",
+                    group,
+                    member
+                ));
+            }
+            if let Some(other) = grouped.insert(member, group) {
+                return Err(anyhow!(
+                    "Crate '{}' belongs to release groups '{}' and '{}'",
+                    member,
+                    other,
+                    group
+                ));
+            }
+        }
+
+        sets.push(LockstepSet {
+            reason: format!("group '{}'", group),
+            members: members.iter().map(String::as_str).collect(),
+        });
+    }
+
+    // Members inheriting `[workspace.package].version` cannot be versioned apart, they form
+    // one set with the groups they belong to
+    let mut inherited: BTreeSet<&str> = BTreeSet::new();
+    for (name, krate) in &ws.crates {
+        if inherits_workspace_version(krate)? {
+            inherited.insert(name);
+        }
+    }
+    if inherited.len() > 1 {
+        loop {
+            let before = inherited.len();
+            sets.retain(|set| {
+                let overlaps = set.members.iter().any(|member| inherited.contains(member));
+                if overlaps {
+                    inherited.extend(set.members.iter().copied());
+                }
+                !overlaps
+            });
+            if inherited.len() == before {
+                break;
+            }
+        }
+        sets.push(LockstepSet {
+            reason: "workspace version".to_string(),
+            members: inherited.into_iter().collect(),
+        });
+    }
+
+    Ok(sets)
+}
+
+fn inherits_workspace_version(krate: &Krate) -> anyhow::Result<bool> {
+    let Some(manifest_path) = &krate.manifest_path else {
+        return Ok(false);
+    };
+    let content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read manifest at {:?}", manifest_path))?;
+    let document = content
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse manifest at {:?}", manifest_path))?;
+
+    Ok(is_inherited(&document, "version"))
+}
+
+/// A crate considered for the release, before its version is settled.
+struct Candidate {
+    old: Version,
+    commits: Vec<ConventionalCommit>,
+    spec: Option<VersionSpec>,
+    reason: String,
+    notes: Vec<String>,
+}
+
+impl Candidate {
+    /// Reads the current version of `name`, and its commits since the last release when a
+    /// repository is given. Without a requested version, the crate is bumped by the largest
+    /// bump the pending changesets give it.
+    fn load(
+        ws: &Workspace,
+        name: &str,
+        repo: Option<&Repository>,
+        options: &ReleaseOptions,
+    ) -> anyhow::Result<Self> {
+        let krate = ws
+            .crates
+            .get(name)
+            .ok_or_else(|| anyhow!("Crate '{}' not found", name))?;
+        let old = Version::parse(&krate.version)
+            .with_context(|| format!("Invalid version for crate '{}'", krate.name))?;
+
+        let commits = match repo {
+            Some(repo) => {
+                let tag = tag_name(&options.tag_template, &krate.name, &old);
+                commits_since_release(repo, krate, &tag)
+                    .with_context(|| format!("Failed to read the history of '{}'", krate.name))?
+            }
+            None => vec![],
+        };
+
+        let mut candidate = Self {
+            old,
+            commits,
+            spec: None,
+            reason: String::new(),
+            notes: vec![],
+        };
+
+        let changesets: Vec<&Changeset> = options
+            .changesets
+            .iter()
+            .filter(|changeset| changeset.bumps.contains_key(name))
+            .collect();
+        let bump = changesets
+            .iter()
+            .filter_map(|changeset| changeset.bumps.get(name).copied())
+            .max();
+        if let (None, Some(bump)) = (&options.version, bump) {
+            candidate.spec = Some(VersionSpec::Bump(bump));
+            candidate.reason = format!("{} from {} changeset(s)", bump, changesets.len());
+            candidate.notes = changesets
+                .iter()
+                .map(|changeset| changeset.summary.clone())
+                .collect();
+        }
+
+        Ok(candidate)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedRelease {
+    pub name: String,
+    pub old: Version,
+    pub new: Version,
+    pub reason: String,
+    /// Commits since the previous release of the crate
+    pub commits: Vec<ConventionalCommit>,
+    /// Release notes written by hand, from changesets and dependency updates
+    pub notes: Vec<String>,
+}
+
+/// New version of every candidate with a bump. A lockstep set shares its largest bump
+/// applied to its highest version, unless an exact version was requested.
+fn settle_versions<'a>(
+    candidates: &BTreeMap<&'a str, Candidate>,
+    sets: &[LockstepSet<'a>],
+    preid: &str,
+) -> BTreeMap<&'a str, Version> {
+    let mut versions: BTreeMap<&str, Version> = candidates
+        .iter()
+        .filter_map(|(name, candidate)| {
+            Some((*name, candidate.spec.as_ref()?.next(&candidate.old, preid)))
+        })
+        .collect();
+
+    for set in sets {
+        let members: Vec<(&str, &Candidate)> = set
+            .members
+            .iter()
+            .filter_map(|member| Some((*member, candidates.get(member)?)))
+            .collect();
+        let specs: Vec<&VersionSpec> = members
+            .iter()
+            .filter_map(|(_, candidate)| candidate.spec.as_ref())
+            .collect();
+        let Some(base) = members.iter().map(|(_, candidate)| &candidate.old).max() else {
+            continue;
+        };
+
+        let exact = specs
+            .iter()
+            .filter_map(|spec| match spec {
+                VersionSpec::Exact(version) => Some(version),
+                _ => None,
+            })
+            .max();
+        let bump = specs
+            .iter()
+            .filter_map(|spec| match spec {
+                VersionSpec::Bump(bump) => Some(*bump),
+                _ => None,
+            })
+            .max();
+        let new = match (exact, bump) {
+            (Some(version), _) => version.clone(),
+            (None, Some(bump)) => bump.apply(base, preid),
+            (None, None) => continue,
+        };
+
+        for (name, _) in members {
+            versions.insert(name, new.clone());
+        }
+    }
+
+    versions
+}
+
+/// Versions every selected crate is released with.
+#[derive(Debug, Default, Serialize)]
+pub struct ReleasePlan {
+    pub releases: Vec<PlannedRelease>,
+    pub mode: VersioningMode,
+}
+
+impl ReleasePlan {
+    /// Plans the release of `crates`. Without a requested version, each crate is bumped
+    /// according to the Conventional Commits touching it since its last release tag, and
+    /// crates without releasable changes are left out.
+    ///
+    /// In fixed mode every member is released with a single version, and so are the members
+    /// of a release group and the members inheriting the workspace version: the largest bump
+    /// of the set is applied to its highest version.
+    pub fn new(
+        ws: &Workspace,
+        crates: &[&Krate],
+        options: &ReleaseOptions,
+    ) -> anyhow::Result<Self> {
+        let use_changesets = options.version.is_none() && !options.changesets.is_empty();
+        let repo = git::open_repository(&ws.path);
+        if options.version.is_none() && !use_changesets && repo.is_err() {
+            return Err(anyhow!(
+                "A git repository is required to infer versions, pass --version"
+            ));
+        }
+        // Changesets replace the commit history as the source of the release notes
+        let history = match use_changesets {
+            true => None,
+            false => repo.as_ref().ok(),
+        };
+
+        for changeset in &options.changesets {
+            if let Some(name) = changeset
+                .bumps
+                .keys()
+                .find(|name| !ws.crates.contains_key(*name))
+            {
+                return Err(anyhow!(
+                    "Changeset '{}' names unknown crate '{}'",
+                    changeset.id,
+                    name
+                ));
+            }
+        }
+
+        let sets = lockstep_sets(ws, options)?;
+        let graph = WorkspaceGraph::from_workspace(ws);
+
+        let selected: BTreeSet<&str> = crates.iter().map(|krate| krate.name.as_str()).collect();
+        let mut considered = selected.clone();
+        for set in &sets {
+            if set.members.iter().any(|member| selected.contains(member)) {
+                considered.extend(set.members.iter().copied());
+            }
+        }
+
+        let mut candidates: BTreeMap<&str, Candidate> = BTreeMap::new();
+        for name in considered {
+            let mut candidate = Candidate::load(ws, name, history, options)?;
+
+            if let Some(spec) = &options.version {
+                candidate.spec = Some(spec.clone());
+                candidate.reason = "requested".to_string();
+            } else if let Some(bump) = suggested_bump(&candidate.commits, &candidate.old) {
+                candidate.spec = Some(VersionSpec::Bump(bump));
+                candidate.reason = format!("{} from {} commit(s)", bump, candidate.commits.len());
+            }
+
+            candidates.insert(name, candidate);
+        }
+
+        // Dependents of a released crate are released as well so they can be published
+        // against its new version, and lockstep sets move as a whole. Versions are settled
+        // again until no more crate is pulled in.
+        let dependent_bump = match &options.version {
+            Some(VersionSpec::Bump(Bump::Prerelease)) => Bump::Prerelease,
+            _ => options.dependent_bump,
+        };
+        let mut versions = settle_versions(&candidates, &sets, &options.preid);
+        loop {
+            let mut changed = false;
+
+            for (name, version) in versions.clone() {
+                for set in sets.iter().filter(|set| set.members.contains(&name)) {
+                    for member in &set.members {
+                        if let Entry::Vacant(entry) = candidates.entry(*member) {
+                            let mut candidate = Candidate::load(ws, member, history, options)?;
+                            if candidate.spec.is_none() {
+                                candidate.reason = set.reason.clone();
+                            }
+                            entry.insert(candidate);
+                            changed = true;
+                        }
+                    }
+                }
+
+                if options.propagation == Propagation::Never {
+                    continue;
+                }
+                for (dependent, kind) in graph.direct_dependents(name) {
+                    let Some((dependent, krate)) = ws.crates.get_key_value(dependent) else {
+                        continue;
+                    };
+                    if kind == DependencyKind::Dev {
+                        continue;
+                    }
+                    // Requirements still matching the new version need no new release
+                    let requirement = krate
+                        .dependencies
+                        .get(name)
+                        .and_then(|dep| VersionReq::parse(&dep.version).ok());
+                    if let (Propagation::Breaking, Some(requirement)) =
+                        (options.propagation, &requirement)
+                    {
+                        if requirement.matches(&version) {
+                            continue;
+                        }
+                    }
+
+                    let candidate = match candidates.entry(dependent.as_str()) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            entry.insert(Candidate::load(ws, dependent, history, options)?)
+                        }
+                    };
+                    let raise = match &candidate.spec {
+                        None => true,
+                        Some(VersionSpec::Bump(bump)) => *bump < dependent_bump,
+                        Some(VersionSpec::Exact(_)) => false,
+                    };
+                    if raise {
+                        candidate.spec = Some(VersionSpec::Bump(dependent_bump));
+                        candidate.reason =
+                            format!("{}, depends on {} {}", dependent_bump, name, version);
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+            versions = settle_versions(&candidates, &sets, &options.preid);
+        }
+
+        let mut plan = ReleasePlan {
+            releases: vec![],
+            mode: options.mode,
+        };
+
+        for (name, candidate) in candidates {
+            let Some(new) = versions.remove(name) else {
+                continue;
+            };
+
+            if new <= candidate.old {
+                return Err(anyhow!(
+                    "New version {} of '{}' must be greater than {}",
+                    new,
+                    name,
+                    candidate.old
+                ));
+            }
+
+            plan.releases.push(PlannedRelease {
+                name: name.to_string(),
+                old: candidate.old,
+                new,
+                reason: candidate.reason,
+                commits: candidate.commits,
+                notes: candidate.notes,
+            });
+        }
+
+        let released_versions: BTreeMap<String, Version> = plan
+            .releases
+            .iter()
+            .map(|release| (release.name.clone(), release.new.clone()))
+            .collect();
+        for release in &mut plan.releases {
+            let updated: Vec<String> = graph
+                .direct_dependencies(&release.name)
+                .into_iter()
+                .filter(|(_, kind)| *kind != DependencyKind::Dev)
+                .filter_map(|(dep, _)| Some(format!("{} {}", dep, released_versions.get(dep)?)))
+                .collect();
+            if !updated.is_empty() {
+                release
+                    .notes
+                    .push(format!("Updated dependencies: {}", updated.join(", ")));
+            }
+        }
+
+        plan.releases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(plan)
+    }
+
+    /// Changesets naming a released crate, without the entries of the released crates: their
+    /// bumps are applied by this release. Fully consumed changesets are left without entries,
+    /// as are the changesets that had none, so they get deleted along.
+    pub fn consume_changesets(&self, changesets: &[Changeset]) -> Vec<Changeset> {
+        changesets
+            .iter()
+            .filter(|changeset| {
+                changeset.bumps.is_empty()
+                    || changeset.bumps.keys().any(|name| self.get(name).is_some())
+            })
+            .map(|changeset| {
+                let mut changeset = changeset.clone();
+                changeset.bumps.retain(|name, _| self.get(name).is_none());
+                changeset
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.releases.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlannedRelease> {
+        self.releases.iter().find(|release| release.name == name)
+    }
+
+    pub fn print(&self) {
+        let width = self
+            .releases
+            .iter()
+            .map(|release| release.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("CRATE".len());
+
+        println!(
+            "{:<width$}  {:<14}  {:<14}  {}",
+            "CRATE".bold(),
+            "OLD".bold(),
+            "NEW".bold(),
+            "REASON".bold()
+        );
+        for release in &self.releases {
+            println!(
+                "{:<width$}  {:<14}  {:<14}  {}",
+                release.name,
+                release.old.to_string(),
+                release.new.to_string().green(),
+                release.reason.dimmed()
+            );
+        }
+    }
+
+    /// Rewrites the member manifests (or `[workspace.package]` for inherited versions) and
+    /// the requirements of every path dependency on a released crate. In fixed mode the
+    /// `[workspace.package]` version follows the shared version as well.
+    pub fn update_manifests(&self, ws: &Workspace) -> anyhow::Result<ManifestSet> {
+        let mut manifests = ManifestSet::default();
+        let mut workspace_version: Option<&Version> = None;
+
+        for release in &self.releases {
+            let krate = ws
+                .crates
+                .get(&release.name)
+                .ok_or_else(|| anyhow!("Crate '{}' not found", release.name))?;
+            let manifest_path = krate
+                .manifest_path
+                .as_ref()
+                .ok_or_else(|| anyhow!("Crate '{}' has no manifest", release.name))?;
+
+            let document = manifests.get_mut(manifest_path)?;
+            if is_inherited(document, "version") {
+                match workspace_version {
+                    Some(version) if version != &release.new => {
+                        return Err(anyhow!(
+                            "Crates inheriting the workspace version must be released with the same version ({} and {} requested)",
+                            version,
+                            release.new
+                        ));
+                    }
+                    _ => workspace_version = Some(&release.new),
+                }
+            } else {
+                set_package_version(document, &release.new.to_string())
+                    .with_context(|| format!("Failed to update {:?}", manifest_path))?;
+            }
+        }
+
+        if self.mode == VersioningMode::Fixed && workspace_version.is_none() {
+            let has_workspace_version = manifests
+                .get_mut(&ws.manifest_path)?
+                .get("workspace")
+                .and_then(|workspace| workspace.get("package"))
+                .and_then(|package| package.get("version"))
+                .is_some();
+            if has_workspace_version {
+                workspace_version = self.releases.first().map(|release| &release.new);
+            }
+        }
+
+        if let Some(version) = workspace_version {
+            set_workspace_version(manifests.get_mut(&ws.manifest_path)?, &version.to_string())?;
+        }
+
+        // Point path dependencies at the new versions
+        let released: BTreeMap<&str, &Version> = self
+            .releases
+            .iter()
+            .map(|release| (release.name.as_str(), &release.new))
+            .collect();
+
+        let mut manifest_paths: Vec<_> = ws
+            .crates
+            .values()
+            .filter_map(|krate| krate.manifest_path.clone())
+            .collect();
+        manifest_paths.push(ws.manifest_path.clone());
+
+        for path in manifest_paths {
+            let document = manifests.get_mut(&path)?;
+            for table in dependency_tables_mut(document) {
+                for (key, entry) in table.iter_mut() {
+                    let name = dependency_name(key.get(), entry).to_string();
+                    let Some(version) = released.get(name.as_str()) else {
+                        continue;
+                    };
+                    if entry.get("path").is_none() {
+                        continue;
+                    }
+
+                    let Some(requirement) = entry.get_mut("version") else {
+                        continue;
+                    };
+                    let current = requirement.as_str().unwrap_or("").to_string();
+                    match updated_requirement(&current, &version.to_string()) {
+                        Some(updated) => set_string(requirement, &updated),
+                        // Ranges are kept as written, as long as they allow the new version
+                        None if VersionReq::parse(&current)
+                            .is_ok_and(|req| req.matches(version)) => {}
+                        None => {
+                            return Err(anyhow!(
+                            "Requirement '{}' on '{}' in {:?} does not allow {}, update it by hand",
+                            current,
+                            name,
+                            path,
+                            version
+                        ))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(manifests)
+    }
+
+    /// Changelog section of every released crate, keyed by crate directory.
+    pub fn changelogs(&self, ws: &Workspace) -> Vec<(PathBuf, String)> {
+        self.releases
+            .iter()
+            .filter_map(|release| match &ws.crates.get(&release.name)?.path {
+                KrateSource::Path(path) => Some((
+                    path.clone(),
+                    changelog_section(&release.new, &release.commits, &release.notes),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Prepends the release notes to the `CHANGELOG.md` of every released crate, returning
+    /// the updated files.
+    pub fn write_changelogs(&self, ws: &Workspace) -> anyhow::Result<Vec<PathBuf>> {
+        let mut written = vec![];
+        for (path, section) in self.changelogs(ws) {
+            prepend_changelog(&path, &section)?;
+            written.push(path.join("CHANGELOG.md"));
+        }
+        Ok(written)
+    }
+
+    /// Commits `paths` as a single release commit. `{releases}` in `template` lists the
+    /// released crates.
+    pub fn commit(
+        &self,
+        repo: &Repository,
+        paths: &[PathBuf],
+        template: &str,
+    ) -> anyhow::Result<Oid> {
+        let releases: Vec<String> = self
+            .releases
+            .iter()
+            .map(|release| format!("{} v{}", release.name, release.new))
+            .collect();
+        let message = template.replace("{releases}", &releases.join(", "));
+
+        git::commit_paths(repo, paths, &message).context("Failed to create the release commit")
+    }
+
+    /// Creates an annotated tag per released crate on `target`, returning the tag names.
+    pub fn tag(
+        &self,
+        repo: &Repository,
+        target: Oid,
+        template: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut tags = vec![];
+        for release in &self.releases {
+            let name = tag_name(template, &release.name, &release.new);
+            let message = format!("Release {} v{}", release.name, release.new);
+            git::create_tag(repo, &name, target, &message)?;
+            tags.push(name);
+        }
+        Ok(tags)
+    }
+}
+
+/// Re-resolves the workspace so `Cargo.lock` records the new versions.
+pub fn refresh_lockfile(ws: &Workspace) -> anyhow::Result<()> {
+    let gctx = GlobalContext::default()?;
+    let cargo_ws = cargo::core::Workspace::new(&ws.manifest_path, &gctx)?;
+    cargo::ops::resolve_ws(&cargo_ws).context("Failed to update Cargo.lock")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(bump: Bump, version: &str) -> String {
+        bump.apply(&Version::parse(version).unwrap(), "alpha")
+            .to_string()
+    }
+
+    #[test]
+    fn bump_releases() {
+        assert_eq!(bump(Bump::Major, "1.2.3"), "2.0.0");
+        assert_eq!(bump(Bump::Minor, "1.2.3"), "1.3.0");
+        assert_eq!(bump(Bump::Patch, "1.2.3"), "1.2.4");
+        assert_eq!(bump(Bump::Minor, "0.1.9"), "0.2.0");
+        assert_eq!(bump(Bump::Patch, "1.2.3+build.5"), "1.2.4");
+    }
+
+    #[test]
+    fn bump_promotes_prereleases() {
+        assert_eq!(bump(Bump::Major, "2.0.0-rc.1"), "2.0.0");
+        assert_eq!(bump(Bump::Major, "2.1.0-rc.1"), "3.0.0");
+        assert_eq!(bump(Bump::Minor, "1.3.0-beta.2"), "1.3.0");
+        assert_eq!(bump(Bump::Minor, "1.3.1-beta.2"), "1.4.0");
+        assert_eq!(bump(Bump::Patch, "1.3.1-beta.2"), "1.3.1");
+    }
+
+    #[test]
+    fn bump_prereleases() {
+        assert_eq!(bump(Bump::Prerelease, "1.2.3"), "1.2.4-alpha.0");
+        assert_eq!(bump(Bump::Prerelease, "1.2.4-alpha.0"), "1.2.4-alpha.1");
+        assert_eq!(bump(Bump::Prerelease, "1.2.4-rc.9"), "1.2.4-rc.10");
+        assert_eq!(bump(Bump::Prerelease, "1.2.4-beta"), "1.2.4-beta.1");
+    }
+
+    #[test]
+    fn consume_changesets_of_released_crates() {
+        let release = |name: &str| PlannedRelease {
+            name: name.to_string(),
+            old: Version::new(0, 1, 0),
+            new: Version::new(0, 2, 0),
+            reason: String::new(),
+            commits: vec![],
+            notes: vec![],
+        };
+        let plan = ReleasePlan {
+            releases: vec![release("api"), release("core")],
+            mode: VersioningMode::default(),
+        };
+        let changeset = |id: &str, names: &[&str]| {
+            let mut changeset = Changeset::new(
+                names
+                    .iter()
+                    .map(|name| (name.to_string(), Bump::Minor))
+                    .collect(),
+                String::new(),
+            );
+            changeset.id = id.to_string();
+            changeset
+        };
+
+        let consumed = plan.consume_changesets(&[
+            changeset("full", &["api", "core"]),
+            changeset("partial", &["cli", "core"]),
+            changeset("untouched", &["cli"]),
+            changeset("empty", &[]),
+        ]);
+        let remaining: Vec<(&str, Vec<&str>)> = consumed
+            .iter()
+            .map(|c| (c.id.as_str(), c.bumps.keys().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            remaining,
+            [
+                ("full", vec![]),
+                ("partial", vec!["cli"]),
+                ("empty", vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn bumps_are_ordered() {
+        assert!(Bump::Prerelease < Bump::Patch);
+        assert!(Bump::Patch < Bump::Minor);
+        assert!(Bump::Minor < Bump::Major);
+        assert_eq!("prerelease".parse::<Bump>().unwrap(), Bump::Prerelease);
+        assert!("huge".parse::<Bump>().is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use anyhow::Context as _;
+use colored::Colorize as _;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+
+use crate::config::Config;
+use crate::graph::WorkspaceGraph;
+use crate::krate::{KrateKind, KrateSource};
+use crate::workspace::Workspace;
+
+/// Report printed by `peniche info`.
+#[derive(Debug, Serialize)]
+pub struct WorkspaceInfo {
+    pub root: PathBuf,
+    pub resolver: Option<String>,
+    pub binaries: usize,
+    pub libraries: usize,
+    /// Keys of `[workspace.package]` members can inherit
+    pub workspace_package: Vec<String>,
+    pub crates: Vec<KrateInfo>,
+    pub commands: Vec<CommandInfo>,
+    pub issues: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KrateInfo {
+    pub name: String,
+    pub version: String,
+    pub kind: KrateKind,
+    /// Directory of the crate, relative to the workspace root
+    pub path: Option<PathBuf>,
+    pub internal_dependencies: usize,
+    pub external_dependencies: usize,
+    /// `[package]` fields declared with `workspace = true`
+    pub inherited_fields: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandInfo {
+    pub name: String,
+    pub depends_on: Vec<String>,
+    pub foreach: bool,
+}
+
+impl Workspace {
+    pub fn info(&self, config: &Config) -> anyhow::Result<WorkspaceInfo> {
+        let root_manifest = read_document(&self.manifest_path)?;
+        let graph = WorkspaceGraph::from_workspace(self);
+        let mut issues = vec![];
+
+        let resolver = root_manifest
+            .get("workspace")
+            .and_then(|ws| ws.get("resolver"))
+            .or_else(|| root_manifest.get("package").and_then(|p| p.get("resolver")))
+            .and_then(|resolver| resolver.as_str())
+            .map(String::from);
+
+        let workspace_package = root_manifest
+            .get("workspace")
+            .and_then(|ws| ws.get("package"))
+            .and_then(Item::as_table_like)
+            .map(|table| table.iter().map(|(key, _)| key.to_string()).collect())
+            .unwrap_or_default();
+
+        let mut names: Vec<&String> = self.crates.keys().collect();
+        names.sort();
+
+        let mut crates = vec![];
+        for name in names {
+            let krate = &self.crates[name];
+            let internal_dependencies = graph.direct_dependencies(name).len();
+
+            let inherited_fields = match &krate.manifest_path {
+                Some(manifest_path) => inherited_fields(&read_document(manifest_path)?),
+                None => vec![],
+            };
+
+            for dep in krate.dependencies.values() {
+                let Some(member) = self.crates.get(&dep.name) else {
+                    continue;
+                };
+
+                if !dep.path.is_local() {
+                    issues.push(format!(
+                        "'{}' depends on workspace member '{}' through a registry instead of its path",
+                        name, dep.name
+                    ));
+                }
+
+                let matches = match (
+                    VersionReq::parse(&dep.version),
+                    Version::parse(&member.version),
+                ) {
+                    (Ok(req), Ok(version)) => req.matches(&version),
+                    _ => true,
+                };
+                if !matches {
+                    issues.push(format!(
+                        "'{}' requires '{}' {} but the workspace has {}",
+                        name, dep.name, dep.version, member.version
+                    ));
+                }
+            }
+
+            crates.push(KrateInfo {
+                name: krate.name.clone(),
+                version: krate.version.clone(),
+                kind: krate.kind,
+                path: match &krate.path {
+                    KrateSource::Path(path) => Some(relative_path(&self.path, path)),
+                    _ => None,
+                },
+                internal_dependencies,
+                external_dependencies: krate.dependencies.len() - internal_dependencies,
+                inherited_fields,
+            });
+        }
+
+        for cycle in graph.cycles(false) {
+            issues.push(format!("Dependency cycle: {}", cycle.join(" -> ")));
+        }
+
+        let mut command_names: Vec<String> = config.cmd.keys().cloned().collect();
+        command_names.sort();
+        if let Err(err) = config.command_graph(&command_names) {
+            issues.push(format!("Invalid [cmd] configuration: {}", err));
+        }
+
+        let commands = command_names
+            .iter()
+            .map(|name| CommandInfo {
+                name: name.clone(),
+                depends_on: config.cmd[name].depends_on().to_vec(),
+                foreach: config.cmd[name].foreach(),
+            })
+            .collect();
+
+        Ok(WorkspaceInfo {
+            root: self.path.clone(),
+            resolver,
+            binaries: crates.iter().filter(|k| k.kind == KrateKind::Bin).count(),
+            libraries: crates.iter().filter(|k| k.kind == KrateKind::Lib).count(),
+            workspace_package,
+            crates,
+            commands,
+            issues,
+        })
+    }
+}
+
+impl WorkspaceInfo {
+    pub fn print(&self) {
+        println!("{} {}", "Root:".bold(), self.root.display());
+        println!(
+            "{} {}",
+            "Resolver:".bold(),
+            self.resolver.as_deref().unwrap_or("1 (default)")
+        );
+        println!(
+            "{} {} ({} lib, {} bin)",
+            "Members:".bold(),
+            self.crates.len(),
+            self.libraries,
+            self.binaries
+        );
+        if !self.workspace_package.is_empty() {
+            println!(
+                "{} {}",
+                "Shared [workspace.package]:".bold(),
+                self.workspace_package.join(", ")
+            );
+        }
+
+        println!();
+        for krate in &self.crates {
+            let path = krate
+                .path
+                .as_ref()
+                .map_or(String::new(), |path| path.display().to_string());
+            println!(
+                "  {} {} {:?} {}",
+                krate.name.bold(),
+                krate.version,
+                krate.kind,
+                path.dimmed()
+            );
+            println!(
+                "    dependencies: {} internal, {} external",
+                krate.internal_dependencies, krate.external_dependencies
+            );
+            if !krate.inherited_fields.is_empty() {
+                println!("    inherited: {}", krate.inherited_fields.join(", "));
+            }
+        }
+
+        if !self.commands.is_empty() {
+            println!();
+            println!("{}", "Scripts:".bold());
+            for command in &self.commands {
+                let mut details = vec![];
+                if !command.depends_on.is_empty() {
+                    details.push(format!("depends on {}", command.depends_on.join(", ")));
+                }
+                if command.foreach {
+                    details.push("foreach".to_string());
+                }
+                println!("  {} {}", command.name, details.join(", ").dimmed());
+            }
+        }
+
+        println!();
+        if self.issues.is_empty() {
+            println!("{}", "No issues detected".green());
+        } else {
+            println!("{}", "Issues:".yellow().bold());
+            for issue in &self.issues {
+                println!("  {}", issue.yellow());
+            }
+        }
+    }
+}
+
+/// `path` relative to `root`, `.` for the root itself. Paths outside of the root are kept.
+fn relative_path(root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn read_document(path: &Path) -> anyhow::Result<DocumentMut> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest at {:?}", path))?
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse manifest at {:?}", path))
+}
+
+fn inherited_fields(manifest: &DocumentMut) -> Vec<String> {
+    manifest
+        .get("package")
+        .and_then(Item::as_table_like)
+        .map(|package| {
+            package
+                .iter()
+                .filter(|(_, value)| value.get("workspace").and_then(Item::as_bool) == Some(true))
+                .map(|(key, _)| key.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// `app` -> `core` with a stale requirement, `cli` -> `core` through the registry, and
+    /// `core` inheriting `[workspace.package]`.
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "Cargo.toml",
+            r#"[workspace]
+members = ["app", "cli", "core"]
+
+[workspace.package]
+version = "0.2.0"
+edition = "2021"
+license = "MIT"
+"#,
+        );
+        write(
+            dir.path(),
+            "core/Cargo.toml",
+            r#"[package]
+name = "core"
+version.workspace = true
+edition.workspace = true
+"#,
+        );
+        write(dir.path(), "core/src/lib.rs", "");
+        write(
+            dir.path(),
+            "app/Cargo.toml",
+            r#"[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+core = { path = "../core", version = "0.1" }
+serde = "1"
+"#,
+        );
+        write(dir.path(), "app/src/main.rs", "fn main() {}");
+        write(
+            dir.path(),
+            "cli/Cargo.toml",
+            r#"[package]
+name = "cli"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+core = "0.2"
+"#,
+        );
+        write(dir.path(), "cli/src/main.rs", "fn main() {}");
+        dir
+    }
+
+    fn workspace(dir: &Path) -> Workspace {
+        Workspace::from_path(&dir.to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn info_lists_members_and_shared_keys() {
+        let dir = fixture();
+        let info = workspace(dir.path()).info(&Config::default()).unwrap();
+
+        assert_eq!(info.resolver, None);
+        assert_eq!(info.workspace_package, ["version", "edition", "license"]);
+        assert_eq!((info.binaries, info.libraries), (2, 1));
+
+        let names: Vec<&str> = info.crates.iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["app", "cli", "core"]);
+
+        let app = &info.crates[0];
+        assert_eq!(app.path.as_deref(), Some(Path::new("app")));
+        assert_eq!(app.kind, KrateKind::Bin);
+        assert_eq!(
+            (app.internal_dependencies, app.external_dependencies),
+            (1, 1)
+        );
+        assert!(app.inherited_fields.is_empty());
+
+        // A registry dependency on a member is not a link
+        let cli = &info.crates[1];
+        assert_eq!(
+            (cli.internal_dependencies, cli.external_dependencies),
+            (0, 1)
+        );
+
+        let core = &info.crates[2];
+        assert_eq!(core.version, "0.2.0");
+        assert_eq!(core.kind, KrateKind::Lib);
+        assert_eq!(core.inherited_fields, ["version", "edition"]);
+    }
+
+    #[test]
+    fn info_reports_issues() {
+        let dir = fixture();
+        let info = workspace(dir.path()).info(&Config::default()).unwrap();
+
+        assert_eq!(info.issues.len(), 2, "{:?}", info.issues);
+        assert!(info
+            .issues
+            .iter()
+            .any(|issue| issue.starts_with("'app' requires 'core'")
+                && issue.ends_with("but the workspace has 0.2.0")));
+        assert!(info.issues.contains(
+            &"'cli' depends on workspace member 'core' through a registry instead of its path"
+                .to_string()
+        ));
+    }
+
+    #[tokio::test]
+    async fn info_lists_commands_and_reports_invalid_ones() {
+        let dir = fixture();
+        write(
+            dir.path(),
+            "Peniche.toml",
+            r#"[cmd]
+test = { command = "cargo test -p {name}", foreach = true }
+start = { command = "serve", depends_on = ["build"] }
+"#,
+        );
+        let config = Config::from_file(dir.path().join("Peniche.toml").to_str())
+            .await
+            .unwrap();
+        let info = workspace(dir.path()).info(&config).unwrap();
+
+        let commands: Vec<(&str, bool)> = info
+            .commands
+            .iter()
+            .map(|command| (command.name.as_str(), command.foreach))
+            .collect();
+        assert_eq!(commands, [("start", false), ("test", true)]);
+        assert_eq!(info.commands[0].depends_on, ["build"]);
+        assert!(info
+            .issues
+            .iter()
+            .any(|issue| issue.starts_with("Invalid [cmd] configuration")));
+    }
+
+    #[test]
+    fn resolver_falls_back_to_the_root_package() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "Cargo.toml",
+            r#"[package]
+name = "root"
+version = "0.1.0"
+edition = "2021"
+resolver = "2"
+
+[workspace]
+"#,
+        );
+        write(dir.path(), "src/lib.rs", "");
+
+        let info = workspace(dir.path()).info(&Config::default()).unwrap();
+        assert_eq!(info.resolver.as_deref(), Some("2"));
+        assert!(info.workspace_package.is_empty());
+        assert_eq!(info.crates[0].path.as_deref(), Some(Path::new(".")));
+        assert!(info.issues.is_empty(), "{:?}", info.issues);
+    }
+}
@@ -1,15 +1,26 @@
 use anyhow::Context as _;
 use cargo::util::important_paths::find_root_manifest_for_wd;
+use cargo::util::toml_mut::manifest::LocalManifest;
 use cargo::GlobalContext;
 use cargo_util::paths::write_atomic;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
-use std::{collections::HashMap, fs::File, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
 use toml_edit::DocumentMut;
 
-use crate::krate::{KrateKind, KrateSource};
+use crate::graph::WorkspaceGraph;
+use crate::krate::dependency::DependencyOptions;
+use crate::krate::{DependencyKind, KrateKind, KrateSource};
+use crate::plugin::hooks::{HookPoint, Hooks, CRATE_ENV, CRATE_PATH_ENV, DEPENDENCY_ENV};
+use crate::release::manifest::{dependency_name, package_dependency_tables_mut};
 use crate::{krate::Krate, mkdirp, resolve_manifest_path};
 
+pub mod info;
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Workspace {
     pub path: PathBuf,
@@ -48,10 +59,19 @@ impl Workspace {
         name: String,
         path: PathBuf,
         kind: KrateKind,
+        hooks: &Hooks,
     ) -> anyhow::Result<Krate> {
-        Krate::create_in_workspace(kind, name, path)
+        Krate::create_in_workspace(kind, name, path, hooks)
     }
-    pub fn remove_member_crate(&mut self, name: &str, delete_files: bool) -> anyhow::Result<bool> {
+
+    /// Removes the crate from the workspace members, between the `pre-delete` and
+    /// `post-delete` hooks, which only run for actual members.
+    pub fn remove_member_crate(
+        &mut self,
+        name: &str,
+        delete_files: bool,
+        hooks: &Hooks,
+    ) -> anyhow::Result<bool> {
         // First, locate the manifest and parse it to ensure everything else can proceed.
         let root_manifest_path =
             find_root_manifest_for_wd(&self.manifest_path).with_context(|| {
@@ -72,9 +92,9 @@ impl Workspace {
             .with_context(|| "Failed to parse the root Cargo.toml into a TOML document")?;
 
         // Check if the crate is actually part of the workspace
-        if !self.crates.contains_key(name) {
+        let Some(krate) = self.crates.get(name) else {
             return Ok(false);
-        }
+        };
 
         // Prepare to update the TOML document but do not modify self.crates yet
         let workspace = workspace_document
@@ -90,38 +110,211 @@ impl Workspace {
         let initial_len = members.len();
         members.retain(|v| v.as_str() != Some(name));
 
-        // If members are modified, proceed with file operations
-        if initial_len != members.len() {
-            write_atomic(
-                root_manifest_path.clone(),
-                workspace_document.to_string().to_string().as_bytes(),
+        // Not listed in the members, nothing to delete and no hook to run
+        if initial_len == members.len() {
+            return Ok(false);
+        }
+
+        let mut vars = vec![(CRATE_ENV, name.to_string())];
+        if let KrateSource::Path(path) = &krate.path {
+            vars.push((CRATE_PATH_ENV, path.to_string_lossy().to_string()));
+        }
+        hooks.run(HookPoint::PreDelete, &vars)?;
+
+        write_atomic(
+            root_manifest_path.clone(),
+            workspace_document.to_string().to_string().as_bytes(),
+        )
+        .with_context(|| {
+            format!(
+                "Failed to write updated Cargo.toml to {:?}",
+                root_manifest_path
             )
-            .with_context(|| {
-                format!(
-                    "Failed to write updated Cargo.toml to {:?}",
-                    root_manifest_path
-                )
-            })?;
+        })?;
 
-            // Now safely remove the crate from the map
-            if let Some(krate) = self.crates.remove(name) {
-                if delete_files {
-                    if let KrateSource::Path(path) = &krate.path {
-                        std::fs::remove_dir_all(path).with_context(|| {
-                            format!("Failed to delete the crate directory for {}", name)
-                        })?;
-                    }
+        // Now safely remove the crate from the map
+        if let Some(krate) = self.crates.remove(name) {
+            if delete_files {
+                if let KrateSource::Path(path) = &krate.path {
+                    std::fs::remove_dir_all(path).with_context(|| {
+                        format!("Failed to delete the crate directory for {}", name)
+                    })?;
                 }
-                Ok(true)
-            } else {
-                // This branch should logically never be hit due to the earlier contains_key check
-                Err(anyhow::anyhow!(
-                    "Failed to find crate in workspace during removal process"
-                ))
             }
+            hooks.run(HookPoint::PostDelete, &vars)?;
+            Ok(true)
         } else {
-            Ok(false)
+            // This branch should logically never be hit due to the earlier contains_key check
+            Err(anyhow::anyhow!(
+                "Failed to find crate in workspace during removal process"
+            ))
+        }
+    }
+
+    /// Declares `dep` in the manifest of `member`. With `options.workspace` the requirement
+    /// is written to `[workspace.dependencies]` and the member inherits it.
+    pub fn add_dependency(
+        &self,
+        member: &Krate,
+        dep: &Krate,
+        options: &DependencyOptions,
+    ) -> anyhow::Result<()> {
+        options.validate()?;
+        if options.workspace {
+            self.declare_workspace_dependency(dep, options)?;
+        }
+
+        member.add_dependency(dep, options)
+    }
+
+    fn declare_workspace_dependency(
+        &self,
+        dep: &Krate,
+        options: &DependencyOptions,
+    ) -> anyhow::Result<()> {
+        let mut root_manifest = LocalManifest::try_new(&self.manifest_path)?;
+        let table = vec!["workspace".to_string(), "dependencies".to_string()];
+        root_manifest.insert_into_table(&table, &dep.as_declared_dependency(options)?)?;
+        root_manifest.write()
+    }
+
+    /// Makes `from` depend on the member `to`, between the `pre-link` and `post-link` hooks,
+    /// refusing links that would close a dependency cycle. Dev-dependencies may form cycles,
+    /// cargo accepts them.
+    pub fn link(
+        &self,
+        from: &Krate,
+        to: &Krate,
+        options: &DependencyOptions,
+        hooks: &Hooks,
+    ) -> anyhow::Result<()> {
+        if options.kind != DependencyKind::Dev {
+            if from.name == to.name {
+                return Err(anyhow::anyhow!("A crate cannot depend on itself"));
+            }
+            if let Some(path) =
+                WorkspaceGraph::from_workspace(self).dependency_path(&to.name, &from.name)
+            {
+                return Err(anyhow::anyhow!(
+                    "Linking would create a dependency cycle: {} -> {}",
+                    from.name,
+                    path.join(" -> ")
+                ));
+            }
+        }
+
+        options.validate()?;
+        if !self.crates.contains_key(&to.name) {
+            return Err(anyhow::anyhow!(
+                "Crate '{}' is not a member of the workspace",
+                to.name
+            ));
+        }
+
+        // The pre-link hooks may abort the link, before any manifest is touched
+        let vars = [
+            (CRATE_ENV, from.name.clone()),
+            (DEPENDENCY_ENV, to.name.clone()),
+        ];
+        hooks.run(HookPoint::PreLink, &vars)?;
+        if options.workspace {
+            self.declare_workspace_dependency(to, options)?;
+        }
+        from.link_to(to, options)?;
+        hooks.run(HookPoint::PostLink, &vars)
+    }
+
+    /// Removes `name` from the manifest of `member`, returning the removed keys. With `prune`,
+    /// the `[workspace.dependencies]` entry goes too once no member inherits it anymore, the
+    /// returned flag telling whether it did. Nothing is pruned when `member` did not declare
+    /// `name`.
+    pub fn remove_dependency(
+        &self,
+        member: &Krate,
+        name: &str,
+        prune: bool,
+    ) -> anyhow::Result<(Vec<String>, bool)> {
+        let removed = member.remove_dependency(name)?;
+        if removed.is_empty() || !prune || self.inherits_workspace_dependency(name)? {
+            return Ok((removed, false));
         }
+
+        let content = std::fs::read_to_string(&self.manifest_path)
+            .with_context(|| format!("Failed to read manifest at {:?}", self.manifest_path))?;
+        let mut document = content
+            .parse::<DocumentMut>()
+            .with_context(|| "Failed to parse the root Cargo.toml into a TOML document")?;
+
+        let Some(dependencies) = document
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("dependencies"))
+            .and_then(|dependencies| dependencies.as_table_like_mut())
+        else {
+            return Ok((removed, false));
+        };
+
+        let keys: Vec<String> = dependencies
+            .iter()
+            .filter(|(key, entry)| dependency_name(key, entry) == name || *key == name)
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for key in &keys {
+            dependencies.remove(key);
+        }
+
+        if !keys.is_empty() {
+            write_atomic(&self.manifest_path, document.to_string().as_bytes()).with_context(
+                || {
+                    format!(
+                        "Failed to write updated Cargo.toml to {:?}",
+                        self.manifest_path
+                    )
+                },
+            )?;
+        }
+
+        Ok((removed, !keys.is_empty()))
+    }
+
+    /// Whether a member still declares `name` with `workspace = true`.
+    fn inherits_workspace_dependency(&self, name: &str) -> anyhow::Result<bool> {
+        for krate in self.crates.values() {
+            let Some(manifest_path) = &krate.manifest_path else {
+                continue;
+            };
+            let content = std::fs::read_to_string(manifest_path)
+                .with_context(|| format!("Failed to read manifest at {:?}", manifest_path))?;
+            let mut document = content
+                .parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse manifest at {:?}", manifest_path))?;
+
+            let inherited = package_dependency_tables_mut(&mut document)
+                .into_iter()
+                .any(|table| {
+                    table.iter().any(|(key, entry)| {
+                        (key == name || dependency_name(key, entry) == name)
+                            && entry.get("workspace").and_then(|value| value.as_bool())
+                                == Some(true)
+                    })
+                });
+            if inherited {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Member crate whose directory contains `file`, the innermost one for nested crates.
+    pub fn owning_krate(&self, file: &Path) -> Option<&Krate> {
+        self.crates
+            .values()
+            .filter_map(|krate| match &krate.path {
+                KrateSource::Path(path) if file.starts_with(path) => Some((path, krate)),
+                _ => None,
+            })
+            .max_by_key(|(path, _)| path.components().count())
+            .map(|(_, krate)| krate)
     }
 
     pub fn from_path(path: &str) -> anyhow::Result<Self> {
@@ -145,3 +338,215 @@ impl Workspace {
         Ok(ws)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Command, Shell};
+    use std::collections::BTreeMap;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Workspace sharing `serde`, inherited by `app` and `cli`, while `core` declares nothing.
+    fn fixture(root: &Path) -> Workspace {
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\", \"cli\", \"core\"]\nresolver = \"2\"\n\n\
+             [workspace.dependencies]\nserde = \"1\"\nlog = \"0.4\"\n",
+        );
+
+        let mut ws = Workspace::new(root.to_path_buf());
+        for (name, dependencies) in [
+            (
+                "app",
+                "serde = { workspace = true }\nlog = { workspace = true }\n",
+            ),
+            (
+                "cli",
+                "serde = { workspace = true, features = [\"derive\"] }\n",
+            ),
+            ("core", ""),
+        ] {
+            let path = root.join(name);
+            write(
+                &path.join("Cargo.toml"),
+                &format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+                    name, dependencies
+                ),
+            );
+            let krate = Krate::new(
+                name.to_string(),
+                "0.1.0".to_string(),
+                KrateSource::Path(path),
+            );
+            ws.crates.insert(name.to_string(), krate);
+        }
+        ws
+    }
+
+    /// Hooks running a `sh` script at each of their points.
+    fn hooks(entries: &[(&str, &str)]) -> Hooks {
+        let mut commands = HashMap::new();
+        let mut points = BTreeMap::new();
+        for (point, script) in entries {
+            let command = Command::Simple {
+                key: point.to_string(),
+                colored_key: Default::default(),
+                command: script.to_string(),
+                working_dir: None,
+                env: None,
+                shell: Shell::Sh,
+            };
+            commands.insert(point.to_string(), command);
+            points.insert(point.to_string(), vec![format!("cmd:{}", point)]);
+        }
+        Hooks::new(&points, &commands, None).unwrap()
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap_or_default()
+    }
+
+    fn members(ws: &Workspace) -> Vec<String> {
+        let document = read(&ws.manifest_path).parse::<DocumentMut>().unwrap();
+        document["workspace"]["members"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|member| member.as_str().map(String::from))
+            .collect()
+    }
+
+    fn shared(ws: &Workspace) -> Vec<String> {
+        let document = std::fs::read_to_string(&ws.manifest_path)
+            .unwrap()
+            .parse::<DocumentMut>()
+            .unwrap();
+        document["workspace"]["dependencies"]
+            .as_table_like()
+            .unwrap()
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn remove_dependency_prunes_unused_workspace_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = fixture(dir.path());
+
+        // `core` never declared `serde`, the shared declaration stays
+        let core = &ws.crates["core"];
+        assert_eq!(
+            ws.remove_dependency(core, "serde", true).unwrap(),
+            (vec![], false)
+        );
+        assert_eq!(shared(&ws), ["serde", "log"]);
+
+        // `cli` still inherits `serde`
+        let app = &ws.crates["app"];
+        let removed = ws.remove_dependency(app, "serde", true).unwrap();
+        assert_eq!(removed, (vec!["serde".to_string()], false));
+        assert_eq!(shared(&ws), ["serde", "log"]);
+
+        let cli = &ws.crates["cli"];
+        let removed = ws.remove_dependency(cli, "serde", true).unwrap();
+        assert_eq!(removed, (vec!["serde".to_string()], true));
+        assert_eq!(shared(&ws), ["log"]);
+
+        // Without `prune` the unused declaration is kept
+        let removed = ws.remove_dependency(app, "log", false).unwrap();
+        assert_eq!(removed, (vec!["log".to_string()], false));
+        assert_eq!(shared(&ws), ["log"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pre_link_hooks_run_before_any_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = fixture(dir.path());
+        let (app, core) = (&ws.crates["app"], &ws.crates["core"]);
+        let options = DependencyOptions {
+            workspace: true,
+            ..Default::default()
+        };
+
+        let root_manifest = read(&ws.manifest_path);
+        let app_manifest = read(app.manifest_path.as_ref().unwrap());
+        let refusing = hooks(&[("pre-link", "exit 1")]);
+        assert!(ws.link(app, core, &options, &refusing).is_err());
+        assert_eq!(read(&ws.manifest_path), root_manifest);
+        assert_eq!(read(app.manifest_path.as_ref().unwrap()), app_manifest);
+
+        let log = dir.path().join("hooks.log");
+        let logging = hooks(&[
+            (
+                "pre-link",
+                format!("echo \"pre $PENICHE_DEPENDENCY\" >> '{}'", log.display()).as_str(),
+            ),
+            (
+                "post-link",
+                format!("echo \"post $PENICHE_DEPENDENCY\" >> '{}'", log.display()).as_str(),
+            ),
+        ]);
+        ws.link(app, core, &options, &logging).unwrap();
+        assert_eq!(read(&log), "pre core\npost core\n");
+        assert_eq!(shared(&ws), ["serde", "log", "core"]);
+        let document = read(app.manifest_path.as_ref().unwrap())
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert_eq!(
+            document["dependencies"]["core"]["workspace"].as_bool(),
+            Some(true)
+        );
+
+        let err = ws.link(app, app, &options, &logging).unwrap_err();
+        assert_eq!(err.to_string(), "A crate cannot depend on itself");
+        assert_eq!(read(&log), "pre core\npost core\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn delete_hooks_only_run_for_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ws = fixture(dir.path());
+        // Known to peniche but missing from the members array
+        let extra = Krate::new(
+            "extra".to_string(),
+            "0.1.0".to_string(),
+            KrateSource::Path(dir.path().join("extra")),
+        );
+        ws.crates.insert("extra".to_string(), extra);
+
+        let log = dir.path().join("hooks.log");
+        let logging = hooks(&[
+            (
+                "pre-delete",
+                format!("echo \"pre $PENICHE_CRATE\" >> '{}'", log.display()).as_str(),
+            ),
+            (
+                "post-delete",
+                format!("echo \"post $PENICHE_CRATE\" >> '{}'", log.display()).as_str(),
+            ),
+        ]);
+        assert!(!ws.remove_member_crate("extra", false, &logging).unwrap());
+        assert!(!ws.remove_member_crate("unknown", false, &logging).unwrap());
+        assert_eq!(read(&log), "");
+
+        let refusing = hooks(&[("pre-delete", "exit 1")]);
+        assert!(ws.remove_member_crate("core", true, &refusing).is_err());
+        assert_eq!(members(&ws), ["app", "cli", "core"]);
+        assert!(ws.crates.contains_key("core"));
+        assert!(dir.path().join("core").exists());
+
+        assert!(ws.remove_member_crate("core", true, &logging).unwrap());
+        assert_eq!(read(&log), "pre core\npost core\n");
+        assert_eq!(members(&ws), ["app", "cli"]);
+        assert!(!ws.crates.contains_key("core"));
+        assert!(!dir.path().join("core").exists());
+    }
+}
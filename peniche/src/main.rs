@@ -1,13 +1,36 @@
-use std::{env::current_dir, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    env::current_dir,
+    io::{IsTerminal as _, Write as _},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use colored::*;
 use peniche_core::{
-    config::Config,
+    config::{Config, FailureMode, RunOptions, CONFIG_FILE},
+    error_msg, filter, git,
+    graph::WorkspaceGraph,
     info_msg,
-    krate::{Krate, KrateKind},
+    krate::{
+        dependency::{parse_dependency_spec, DependencyOptions},
+        DependencyKind, Krate, KrateKind, KrateSource,
+    },
     log::handle_error,
+    plugin::{
+        self,
+        hooks::{HookPoint, RELEASES_ENV},
+        PluginKind, PluginOrigin,
+    },
+    publish::{self, PublishOptions, PublishStatus},
+    registry,
+    release::{
+        self,
+        changeset::{self, Changeset},
+        Bump, Propagation, ReleaseOptions, ReleasePlan,
+    },
     success_msg,
     workspace::Workspace,
 };
@@ -16,8 +39,21 @@ use peniche_core::{
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
-    #[clap(short, long, default_value = "Peniche.toml")]
-    config: String,
+    #[clap(
+        short,
+        long,
+        env = "PENICHE_CONFIG",
+        help = "Configuration file, Peniche.toml being looked up from the current directory up to the workspace root by default"
+    )]
+    config: Option<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        help = "Answer yes to confirmations: release plans and the manifest edits proposed by WebAssembly plugins"
+    )]
+    yes: bool,
 
     #[clap(subcommand)]
     command: Commands,
@@ -26,7 +62,10 @@ pub struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Show informations about the workspace
-    Info,
+    Info {
+        #[clap(long, help = "Print the report as JSON")]
+        json: bool,
+    },
     /// Initialize a new cargo workspace
     Init {
         #[clap(
@@ -62,11 +101,15 @@ enum Commands {
     Install {
         #[clap(help = "One (or more) names for the crate(s) to install globally")]
         names: Vec<String>,
+        #[clap(flatten)]
+        filter: FilterArgs,
     },
     #[clap(alias = "u")]
     Uninstall {
         #[clap(help = "One (or more) names for the crate(s) to uninstall globally")]
         names: Vec<String>,
+        #[clap(flatten)]
+        filter: FilterArgs,
     },
     #[clap(alias = "r")]
     Run {
@@ -74,6 +117,21 @@ enum Commands {
         names: Vec<String>,
         #[clap(long, help = "List all available commands", action = clap::ArgAction::SetTrue)]
         list: bool,
+        #[clap(
+            long,
+            help = "Kill the other commands as soon as one fails (default)",
+            conflicts_with = "keep_going"
+        )]
+        fail_fast: bool,
+        #[clap(
+            long,
+            help = "Keep running the commands that do not depend on a failed one"
+        )]
+        keep_going: bool,
+        #[clap(long, help = "Run the scripts once in every workspace member")]
+        each: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
     },
     /// Remove a crate from the workspace, optionally DELETING THE CRATE DIRECTORY!!!!
     #[clap(alias = "rm")]
@@ -87,32 +145,252 @@ enum Commands {
             default_value_t = false
         )]
         rmdir: bool,
+
+        #[clap(flatten)]
+        filter: FilterArgs,
     },
     /// List all crates in the workspace
     #[clap(alias = "ls")]
-    ListCrates,
+    ListCrates {
+        #[clap(flatten)]
+        filter: FilterArgs,
+        #[clap(long, help = "Also list the dependencies of each crate")]
+        deps: bool,
+    },
     /// Add a workspace crate as a dependency of another workspace crate
     #[clap(alias = "ln")]
-    Link { from: String, to: String },
+    Link {
+        #[clap(help = "Member crate getting the dependency")]
+        from: String,
+        #[clap(help = "Member crate to depend on")]
+        to: String,
+        #[clap(long, conflicts_with = "build", help = "Link as a dev-dependency")]
+        dev: bool,
+        #[clap(long, help = "Link as a build-dependency")]
+        build: bool,
+        #[clap(long, value_delimiter = ',', help = "Features to enable")]
+        features: Vec<String>,
+        #[clap(long, help = "Disable the default features")]
+        no_default_features: bool,
+        #[clap(long, help = "Mark the dependency as optional")]
+        optional: bool,
+        #[clap(long, help = "Import the crate under another name")]
+        rename: Option<String>,
+        #[clap(
+            long,
+            help = "Declare the path in [workspace.dependencies] and inherit it"
+        )]
+        workspace: bool,
+    },
+    /// Remove the dependency of a member crate on another member
+    Unlink {
+        from: String,
+        to: String,
+        #[clap(
+            long,
+            help = "Also drop the [workspace.dependencies] entry when no member uses it anymore"
+        )]
+        prune: bool,
+    },
+    /// Remove dependencies from a member crate, in every dependency table
+    Remove {
+        #[clap(help = "Member crate to remove the dependencies from")]
+        krate: String,
+        #[clap(required = true, help = "Names of the dependencies to remove")]
+        deps: Vec<String>,
+        #[clap(
+            long,
+            help = "Also drop the [workspace.dependencies] entries no member uses anymore"
+        )]
+        prune: bool,
+    },
+    /// Add registry dependencies to a member crate
+    Add {
+        #[clap(help = "Member crate to add the dependencies to")]
+        krate: String,
+        #[clap(
+            required = true,
+            help = "Dependencies as name or name@requirement (serde@1), the latest version by default"
+        )]
+        deps: Vec<String>,
+        #[clap(long, value_delimiter = ',', help = "Features to enable")]
+        features: Vec<String>,
+        #[clap(long, conflicts_with = "build", help = "Add as a dev-dependency")]
+        dev: bool,
+        #[clap(long, help = "Add as a build-dependency")]
+        build: bool,
+        #[clap(long, help = "Mark the dependencies as optional")]
+        optional: bool,
+        #[clap(long, help = "Add to a platform specific table, such as 'cfg(unix)'")]
+        target: Option<String>,
+        #[clap(
+            long,
+            help = "Declare the requirements in [workspace.dependencies] and inherit them"
+        )]
+        workspace: bool,
+    },
+    /// Export the dependency graph between workspace members
+    Graph {
+        #[clap(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+    },
+    /// List the crates affected by the changes since a git reference
+    Affected {
+        #[clap(
+            long,
+            default_value = "origin/main",
+            help = "Git reference to compare with"
+        )]
+        base: String,
+        #[clap(
+            long,
+            help = "Only list crates owning a changed file, not their dependents"
+        )]
+        direct: bool,
+        #[clap(long, help = "Print the changed crates and their dependents as JSON")]
+        json: bool,
+    },
+    /// Publish workspace crates to a registry, dependencies first
+    Publish {
+        #[clap(help = "Crates to publish (every member when no crate or filter is given)")]
+        names: Vec<String>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        #[clap(long, help = "Alternative registry to publish to")]
+        registry: Option<String>,
+        #[clap(long, help = "Package and verify the crates without uploading them")]
+        dry_run: bool,
+        #[clap(long, help = "Allow publishing with uncommitted changes")]
+        allow_dirty: bool,
+        #[clap(long, help = "Do not build the packaged crates before publishing")]
+        no_verify: bool,
+        #[clap(
+            long,
+            default_value_t = 300,
+            help = "Seconds to wait for each crate to appear in the index"
+        )]
+        timeout: u64,
+    },
+    /// Record the crates a change should be released in, with a summary for their changelogs
+    Changeset {
+        #[clap(
+            short,
+            long = "bump",
+            value_name = "CRATE=LEVEL",
+            help = "Crate to release and its bump (major, minor, patch or prerelease), prompted when missing"
+        )]
+        bumps: Vec<String>,
+        #[clap(short, long, help = "Summary of the change, prompted when missing")]
+        message: Option<String>,
+    },
+    /// Inspect the layered configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Manage the external `peniche-<name>` plugins
+    Plugins {
+        #[clap(subcommand)]
+        command: PluginsCommand,
+    },
+    /// Run the `peniche-<name>` plugin found in .peniche/plugins or on PATH
+    #[clap(external_subcommand)]
+    External(Vec<String>),
     /// Perform a release
     Release {
-        /// Release version type (major, minor, patch)
+        /// Release version type (major, minor, patch, prerelease) or an exact version,
+        /// inferred from Conventional Commits when missing
         #[clap(short, long)]
-        version: String,
+        version: Option<String>,
+        #[clap(help = "Crates to release (every member when no crate or filter is given)")]
+        names: Vec<String>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        #[clap(long, default_value = "alpha", help = "Identifier of new prereleases")]
+        preid: String,
+        #[clap(long, help = "Show the changes without writing anything")]
+        dry_run: bool,
+        #[clap(long, help = "Do not update the CHANGELOG.md of released crates")]
+        no_changelog: bool,
+        #[clap(
+            long,
+            requires = "no_tag",
+            help = "Do not create the release commit (requires --no-tag)"
+        )]
+        no_commit: bool,
+        #[clap(long, help = "Do not create the release tags")]
+        no_tag: bool,
+        #[clap(long, help = "Release from a dirty working tree or another branch")]
+        force: bool,
+        #[clap(long, help = "Do not release the dependents of the released crates")]
+        no_propagate: bool,
+        #[clap(
+            long,
+            help = "Minimum bump of the released dependents (patch by default)"
+        )]
+        dependent_bump: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print every configuration value set by the user, workspace and crate files or by the
+    /// environment
+    Show {
+        #[clap(long, help = "Print the file or environment each value comes from")]
+        origin: bool,
+        #[clap(long, help = "Print the values as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PluginsCommand {
+    /// List the discovered plugins
+    List {
+        #[clap(long, help = "Print the plugins as JSON")]
+        json: bool,
     },
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Mermaid,
+    Json,
+}
+
+/// Crate selection shared by every command targeting workspace members
+#[derive(Args, Debug, Default)]
+struct FilterArgs {
+    #[clap(
+        short = 'F',
+        long = "filter",
+        help = "Select crates: 'core', 'svc-*', 'core...' (dependents), '...core' (dependencies), '[origin/main]' (changed), '!core' (exclude)"
+    )]
+    filters: Vec<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let config = Config::from_file(None).await?;
 
     match cli.command {
-        Commands::Info => {
-            // let current_dir = handle_error(get_current_dir(), "Could not get current directory")?;
-            // let ws = handle_error(Workspace::from_path(&current_dir.to_string_lossy()), "Failed to load workspace")?;
-            info_msg!("Workspace info:");
-            todo!()
+        Commands::Info { json } => {
+            let config = load_config(cli.config.as_deref()).await?;
+            let current_dir = handle_error(get_current_dir(), "Could not get current directory")?;
+            let ws = handle_error(
+                Workspace::from_path(&current_dir.to_string_lossy()),
+                "Failed to load workspace",
+            )?;
+            let info = handle_error(ws.info(&config), "Failed to inspect workspace")?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                info_msg!("Workspace info:");
+                info.print();
+            }
         }
         Commands::Init { name, path } => {
             let current_dir = get_current_dir()?;
@@ -124,45 +402,61 @@ async fn main() -> Result<()> {
             success_msg!("Initialized workspace at {}", path.display());
         }
         Commands::New { bin: _, lib, names } => {
+            let config = load_config(cli.config.as_deref()).await?;
             let current_dir = get_current_dir()?;
             let ws = Workspace::from_path(&current_dir.to_string_lossy())?;
+            let hooks = config.hooks(Some(ws.path.clone()))?.assume_yes(cli.yes);
 
             for name in names {
                 let kind = if lib { KrateKind::Lib } else { KrateKind::Bin };
                 let path = ws.path.clone().join(&name);
                 info_msg!("Creating {} ({:?}) at {:?}", name, kind, path);
                 handle_error(
-                    Krate::create_in_workspace(kind, name.clone(), path),
+                    Krate::create_in_workspace(kind, name.clone(), path, &hooks),
                     &format!("Failed to create crate '{}'", name),
                 )?;
                 success_msg!("Created new crate '{}'", name.bold().underline());
             }
         }
-        Commands::Install { names } => {
+        Commands::Install { names, filter } => {
             let current_dir = get_current_dir()?;
             let ws = Workspace::from_path(&current_dir.to_string_lossy())?;
 
-            for name in names {
-                let krate = ws.crates.get(&name).unwrap();
+            for krate in select_crates(&ws, &names, &filter)? {
                 handle_error(
                     krate.install_krate_globally(),
-                    &format!("Failed to install crate {} globally", name),
+                    &format!("Failed to install crate {} globally", krate.name),
                 )?;
             }
         }
-        Commands::Uninstall { names } => {
+        Commands::Uninstall { names, filter } => {
             let current_dir = get_current_dir()?;
             let ws = Workspace::from_path(&current_dir.to_string_lossy())?;
 
-            for name in names {
-                let krate = ws.crates.get(&name).unwrap();
+            for krate in select_crates(&ws, &names, &filter)? {
                 handle_error(
                     krate.uninstall_krate_globally(),
-                    &format!("Failed to uninstall crate {} globally", name),
+                    &format!("Failed to uninstall crate {} globally", krate.name),
                 )?;
             }
         }
-        Commands::Run { names, list } => {
+        Commands::Run {
+            names,
+            list,
+            fail_fast: _,
+            keep_going,
+            each,
+            filter,
+        } => {
+            let config = load_config(cli.config.as_deref()).await?;
+            // Commands may also come from the user or crate configuration files
+            if config.path.is_none() && config.cmd.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No {} found from the current directory up to the workspace root, pass --config",
+                    CONFIG_FILE
+                ));
+            }
+
             if list || names.is_empty() {
                 // If the list flag is set, display all available commands
                 info_msg!("Available commands:");
@@ -172,38 +466,93 @@ async fn main() -> Result<()> {
             } else {
                 // Otherwise, execute specified commands
                 if !names.is_empty() {
-                    config.execute_commands_in_parallel(names).await;
+                    let mode = if keep_going {
+                        FailureMode::KeepGoing
+                    } else {
+                        FailureMode::FailFast
+                    };
+                    let per_crate = each
+                        || config.command_graph(&names).is_ok_and(|graph| {
+                            graph.node_weights().any(|name| config.cmd[name].foreach())
+                        });
+                    if !per_crate && !filter.filters.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "--filter only applies to per-crate commands, pass --each or set foreach = true on the command"
+                        ));
+                    }
+
+                    // Per-crate commands need the workspace members
+                    let ws = if per_crate {
+                        Some(Workspace::from_path(&get_current_dir()?.to_string_lossy())?)
+                    } else {
+                        None
+                    };
+                    let crates = match &ws {
+                        Some(ws) => filter::select_crates(ws, &filter.filters)?,
+                        None => vec![],
+                    };
+
+                    let options = RunOptions { mode, each, crates };
+                    let report = handle_error(
+                        config.execute_commands_in_parallel(names, options).await,
+                        "Failed to run commands",
+                    )?;
+                    report.print_summary();
+                    if !report.is_success() {
+                        std::process::exit(report.exit_code());
+                    }
                 } else {
                     println!("No command specified to run.");
                 }
             }
         }
-        Commands::Delete { names, rmdir } => {
+        Commands::Delete {
+            names,
+            rmdir,
+            filter,
+        } => {
             let mut ws = Workspace::from_path(&current_dir().unwrap().to_string_lossy())?;
+            let names: Vec<String> = select_crates(&ws, &names, &filter)?
+                .iter()
+                .map(|krate| krate.name.clone())
+                .collect();
+            let config = load_config(cli.config.as_deref()).await?;
+            let hooks = config.hooks(Some(ws.path.clone()))?.assume_yes(cli.yes);
             for name in names {
                 handle_error(
-                    ws.remove_member_crate(&name, rmdir),
+                    ws.remove_member_crate(&name, rmdir, &hooks),
                     &format!("Failed to remove crate '{}'", name),
                 )?;
                 success_msg!("Removed crate '{}'", name.bold().underline());
             }
         }
-        Commands::ListCrates => {
+        Commands::ListCrates { filter, deps } => {
             let ws = Workspace::from_path(&current_dir().unwrap().to_string_lossy())?;
-            for (_, krate) in ws.crates {
-                let path = match krate.path {
-                    peniche_core::krate::KrateSource::Registry => "cargo",
-                    peniche_core::krate::KrateSource::Path(path) => {
-                        &path.to_string_lossy().to_string()
-                    }
-                    peniche_core::krate::KrateSource::Git(repo) => &repo.to_string(),
-                    peniche_core::krate::KrateSource::Workspace => "workspace",
-                };
-                info_msg!("{} ({}) - {}", krate.name.bold(), krate.version, path);
+            for krate in filter::select_crates(&ws, &filter.filters)? {
+                info_msg!("{} ({}) - {}", krate.name.bold(), krate.version, krate.path);
+                if !deps {
+                    continue;
+                }
+
+                let mut dependencies: Vec<&Krate> = krate.dependencies.values().collect();
+                dependencies.sort_by(|a, b| (a.dep_kind, &a.name).cmp(&(b.dep_kind, &b.name)));
+                for dep in dependencies {
+                    println!("    {}", describe_dependency(dep));
+                }
             }
         }
-        Commands::Link { from, to } => {
-            let ws = Workspace::from_path(&current_dir().unwrap().to_string_lossy())?;
+        Commands::Link {
+            from,
+            to,
+            dev,
+            build,
+            features,
+            no_default_features,
+            optional,
+            rename,
+            workspace,
+        } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
             let from_krate = ws
                 .crates
                 .get(&from)
@@ -213,21 +562,608 @@ async fn main() -> Result<()> {
                 .get(&to)
                 .ok_or_else(|| anyhow::anyhow!("Crate '{}' not found", to))?;
 
+            let options = DependencyOptions {
+                kind: dependency_kind(dev, build),
+                features,
+                no_default_features,
+                optional,
+                rename,
+                workspace,
+                ..Default::default()
+            };
+            let config = load_config(cli.config.as_deref()).await?;
+            let hooks = config.hooks(Some(ws.path.clone()))?.assume_yes(cli.yes);
             handle_error(
-                from_krate.link_to(to_krate),
+                ws.link(from_krate, to_krate, &options, &hooks),
                 &format!("Failed to link '{}' to '{}'", from, to),
             )?;
             success_msg!("Linked '{}' to '{}'", from.bold(), to.bold());
         }
-        Commands::Release { version } => {
-            // Implement release logic
-            success_msg!("Released version {}", version);
-            todo!()
+        Commands::Unlink { from, to, prune } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let from_krate = ws
+                .crates
+                .get(&from)
+                .ok_or_else(|| anyhow::anyhow!("Crate '{}' not found", from))?;
+            if !ws.crates.contains_key(&to) {
+                return Err(anyhow::anyhow!("Crate '{}' not found", to));
+            }
+
+            let (removed, pruned) = handle_error(
+                ws.remove_dependency(from_krate, &to, prune),
+                &format!("Failed to unlink '{}' from '{}'", to, from),
+            )?;
+            if removed.is_empty() {
+                return Err(anyhow::anyhow!("'{}' does not depend on '{}'", from, to));
+            }
+            success_msg!("Unlinked '{}' from '{}'", to.bold(), from.bold());
+            if pruned {
+                info_msg!("Removed '{}' from [workspace.dependencies]", to);
+            }
+        }
+        Commands::Remove { krate, deps, prune } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let member = ws
+                .crates
+                .get(&krate)
+                .ok_or_else(|| anyhow::anyhow!("Crate '{}' not found", krate))?;
+
+            for dep in deps {
+                let (removed, pruned) = handle_error(
+                    ws.remove_dependency(member, &dep, prune),
+                    &format!("Failed to remove '{}' from '{}'", dep, krate),
+                )?;
+                if removed.is_empty() {
+                    error_msg!("'{}' does not depend on '{}'", krate, dep);
+                    continue;
+                }
+                success_msg!("Removed {} from '{}'", dep.bold(), krate.bold());
+                if pruned {
+                    info_msg!("Removed '{}' from [workspace.dependencies]", dep);
+                }
+            }
+        }
+        Commands::Add {
+            krate,
+            deps,
+            features,
+            dev,
+            build,
+            optional,
+            target,
+            workspace,
+        } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let member = ws
+                .crates
+                .get(&krate)
+                .ok_or_else(|| anyhow::anyhow!("Crate '{}' not found", krate))?;
+
+            let options = DependencyOptions {
+                kind: dependency_kind(dev, build),
+                features,
+                optional,
+                target,
+                workspace,
+                ..Default::default()
+            };
+
+            for spec in deps {
+                let (name, requirement) = parse_dependency_spec(&spec)?;
+                let requirement = match requirement {
+                    Some(requirement) => requirement,
+                    None => handle_error(
+                        registry::find_latest_version(None, &name),
+                        &format!("Failed to look up the latest version of '{}'", name),
+                    )?
+                    .to_string(),
+                };
+
+                let dep = Krate::new(name.clone(), requirement.clone(), KrateSource::Registry);
+                handle_error(
+                    ws.add_dependency(member, &dep, &options),
+                    &format!("Failed to add '{}' to '{}'", name, krate),
+                )?;
+                success_msg!(
+                    "Added {}@{} to '{}'",
+                    name.bold(),
+                    requirement,
+                    krate.bold()
+                );
+            }
+        }
+        Commands::Graph { format } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let graph = WorkspaceGraph::from_workspace(&ws);
+            match format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Mermaid => print!("{}", graph.to_mermaid()),
+                GraphFormat::Json => println!("{}", graph.to_json()?),
+            }
+        }
+        Commands::Affected { base, direct, json } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let affected = handle_error(
+                git::affected_crates(&ws, &base),
+                &format!("Failed to compute the crates affected since '{}'", base),
+            )?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&affected)?);
+            } else {
+                let names = if direct {
+                    affected.changed
+                } else {
+                    affected.all()
+                };
+                // One name per line, easy to consume from CI scripts
+                for name in names {
+                    println!("{}", name);
+                }
+            }
+        }
+        Commands::Publish {
+            names,
+            filter,
+            registry,
+            dry_run,
+            allow_dirty,
+            no_verify,
+            timeout,
+        } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            let mut crates = select_crates(&ws, &names, &filter)?;
+            if names.is_empty() && filter.filters.is_empty() {
+                crates = ws.crates.values().collect();
+            }
+
+            let options = PublishOptions {
+                registry,
+                dry_run,
+                allow_dirty,
+                verify: !no_verify,
+                timeout: Duration::from_secs(timeout),
+            };
+            let statuses = handle_error(
+                publish::publish_crates(&ws, &crates, &options),
+                "Failed to publish the workspace",
+            )?;
+
+            let count = |wanted: PublishStatus| {
+                statuses
+                    .iter()
+                    .filter(|(_, status)| *status == wanted)
+                    .count()
+            };
+            let (published, packaged) = (
+                count(PublishStatus::Published),
+                count(PublishStatus::DryRun),
+            );
+            let skipped = statuses.len() - published - packaged;
+            match dry_run {
+                true => success_msg!(
+                    "{} crate(s) would be published, {} skipped",
+                    packaged,
+                    skipped
+                ),
+                false => success_msg!("{} crate(s) published, {} skipped", published, skipped),
+            }
+        }
+        Commands::Changeset { bumps, message } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+
+            let bumps = match bumps.is_empty() {
+                true => prompt_bumps(&ws)?,
+                false => bumps
+                    .iter()
+                    .map(|bump| {
+                        let (name, level) = bump.split_once('=').ok_or_else(|| {
+                            anyhow::anyhow!("Invalid bump '{}', expected CRATE=LEVEL", bump)
+                        })?;
+                        Ok((name.to_string(), level.parse::<Bump>()?))
+                    })
+                    .collect::<Result<BTreeMap<String, Bump>>>()?,
+            };
+            if bumps.is_empty() {
+                info_msg!("No crate selected, no changeset written");
+                return Ok(());
+            }
+
+            let summary = match message {
+                Some(message) => message,
+                None => prompt("Summary")?,
+            };
+
+            let mut changeset = Changeset::new(bumps, summary);
+            let path = handle_error(changeset.write(&ws), "Failed to write the changeset")?;
+            success_msg!("Created changeset {}", path.display());
+        }
+        Commands::Config {
+            command: ConfigCommand::Show { origin, json },
+        } => {
+            let config = load_config(cli.config.as_deref()).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&config.entries)?);
+            } else if config.entries.is_empty() {
+                info_msg!("No configuration value is set");
+            } else {
+                for entry in &config.entries {
+                    match (origin, &entry.origin) {
+                        (true, Some(from)) => println!(
+                            "{} = {} {}",
+                            entry.key,
+                            entry.value,
+                            format!("# {}", from).dimmed()
+                        ),
+                        _ => println!("{} = {}", entry.key, entry.value),
+                    }
+                }
+            }
+        }
+        Commands::Plugins {
+            command: PluginsCommand::List { json },
+        } => {
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy()).ok();
+            let plugins = plugin::discover_plugins(ws.as_ref().map(|ws| ws.path.as_path()));
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&plugins)?);
+            } else if plugins.is_empty() {
+                info_msg!("No plugin found in {} or on PATH", plugin::PLUGIN_DIR);
+            } else {
+                for plugin in plugins {
+                    let origin = match plugin.origin {
+                        PluginOrigin::Workspace => "workspace",
+                        PluginOrigin::Path => "PATH",
+                    };
+                    let kind = match plugin.kind {
+                        PluginKind::Executable => "",
+                        PluginKind::Wasm => ", wasm",
+                    };
+                    info_msg!(
+                        "{} ({}{}) - {}",
+                        plugin.name.bold(),
+                        origin,
+                        kind,
+                        plugin.path.display()
+                    );
+                }
+            }
+        }
+        Commands::External(args) => {
+            let (name, args) = args
+                .split_first()
+                .ok_or_else(|| anyhow::anyhow!("Missing plugin name"))?;
+            // Plugins may run outside of a workspace, they just get no model then
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy()).ok();
+
+            let plugin = plugin::find_plugin(name, ws.as_ref().map(|ws| ws.path.as_path()))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown command '{}', and no {}{} plugin was found",
+                        name,
+                        plugin::PLUGIN_PREFIX,
+                        name
+                    )
+                })?;
+            let code = handle_error(
+                plugin.run(args, ws.as_ref(), cli.yes),
+                &format!("Failed to run plugin '{}'", name),
+            )?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Release {
+            version,
+            names,
+            filter,
+            preid,
+            dry_run,
+            no_changelog,
+            no_commit,
+            no_tag,
+            force,
+            no_propagate,
+            dependent_bump,
+        } => {
+            let config = load_config(cli.config.as_deref()).await?;
+            let ws = Workspace::from_path(&get_current_dir()?.to_string_lossy())?;
+            // An explicit version leaves the pending changesets for a later release
+            let changesets = match version {
+                Some(_) => vec![],
+                None => handle_error(
+                    changeset::pending_changesets(&ws),
+                    "Failed to read the pending changesets",
+                )?,
+            };
+            let options = ReleaseOptions {
+                version: version.map(|version| version.parse()).transpose()?,
+                preid,
+                tag_template: config.release.tag.clone(),
+                mode: config.release.mode,
+                groups: config.release.groups.clone(),
+                changesets,
+                propagation: match no_propagate {
+                    true => Propagation::Never,
+                    false => config.release.propagate,
+                },
+                dependent_bump: match dependent_bump {
+                    Some(bump) => bump.parse()?,
+                    None => config.release.dependent_bump,
+                },
+            };
+
+            let repo = if no_commit {
+                None
+            } else {
+                Some(handle_error(
+                    git::open_repository(&ws.path),
+                    "Releases are committed and tagged with git, pass --no-commit --no-tag to skip",
+                )?)
+            };
+
+            if let (Some(repo), false, false) = (&repo, force, dry_run) {
+                handle_error(git::ensure_clean(repo), "Refusing to release, use --force")?;
+
+                let branch = git::current_branch(repo);
+                if let Some(expected) = &config.release.branch {
+                    if branch.as_ref() != Some(expected) {
+                        error_msg!(
+                            "Releases must be made from '{}' (currently on {})",
+                            expected,
+                            branch.as_deref().unwrap_or("a detached HEAD")
+                        );
+                        return Err(anyhow::anyhow!("Refusing to release, use --force"));
+                    }
+                }
+            }
+
+            let mut crates = select_crates(&ws, &names, &filter)?;
+            if names.is_empty() && filter.filters.is_empty() {
+                crates = ws.crates.values().collect();
+            }
+
+            let plan = handle_error(
+                ReleasePlan::new(&ws, &crates, &options),
+                "Failed to plan the release",
+            )?;
+            if plan.is_empty() {
+                info_msg!("Nothing to release");
+                return Ok(());
+            }
+            plan.print();
+            let consumed = plan.consume_changesets(&options.changesets);
+
+            if !dry_run && !cli.yes && std::io::stdin().is_terminal() {
+                let answer = prompt("Apply this release plan? [y/N]")?;
+                if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+                    info_msg!("Release aborted");
+                    return Ok(());
+                }
+            }
+
+            let manifests =
+                handle_error(plan.update_manifests(&ws), "Failed to update the manifests")?;
+
+            if dry_run {
+                println!();
+                manifests.print_diff();
+                if !no_changelog {
+                    for (path, section) in plan.changelogs(&ws) {
+                        println!(
+                            "\n{}",
+                            path.join("CHANGELOG.md").display().to_string().bold()
+                        );
+                        println!("{}", section);
+                    }
+                }
+                for changeset in &consumed {
+                    match changeset.bumps.is_empty() {
+                        true => info_msg!("Would consume changeset {}", changeset.id),
+                        false => info_msg!(
+                            "Would keep changeset {} for {}",
+                            changeset.id,
+                            changeset
+                                .bumps
+                                .keys()
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    }
+                }
+                info_msg!("Dry run, nothing was written");
+                return Ok(());
+            }
+
+            let hooks = config.hooks(Some(ws.path.clone()))?.assume_yes(cli.yes);
+            let releases = plan
+                .releases
+                .iter()
+                .map(|release| format!("{}@{}", release.name, release.new))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let vars = [(RELEASES_ENV, releases)];
+            handle_error(
+                hooks.run(HookPoint::PreRelease, &vars),
+                "Release aborted by a hook",
+            )?;
+
+            let mut changed = manifests.changed_paths();
+            handle_error(manifests.write(), "Failed to write the manifests")?;
+            if !no_changelog {
+                changed.extend(handle_error(
+                    plan.write_changelogs(&ws),
+                    "Failed to write the changelogs",
+                )?);
+            }
+            changed.extend(handle_error(
+                changeset::update_changesets(&ws, &consumed),
+                "Failed to update the consumed changesets",
+            )?);
+            handle_error(
+                release::refresh_lockfile(&ws),
+                "Failed to refresh Cargo.lock",
+            )?;
+            let lockfile = ws.path.join("Cargo.lock");
+            if lockfile.exists() {
+                changed.push(lockfile);
+            }
+
+            for release in &plan.releases {
+                success_msg!("Released {} {}", release.name.bold(), release.new);
+            }
+
+            // Tags point at the release commit, --no-commit requires --no-tag
+            if let Some(repo) = &repo {
+                let commit = handle_error(
+                    plan.commit(repo, &changed, &config.release.commit_message),
+                    "Failed to commit the release",
+                )?;
+                success_msg!("Created release commit {}", commit);
+
+                if !no_tag {
+                    let tags = handle_error(
+                        plan.tag(repo, commit, &config.release.tag),
+                        "Failed to tag the release",
+                    )?;
+                    for tag in tags {
+                        success_msg!("Created tag {}", tag.bold());
+                    }
+                }
+            }
+
+            handle_error(
+                hooks.run(HookPoint::PostRelease, &vars),
+                "Failed to run the post-release hooks",
+            )?;
         }
     }
     Ok(())
 }
 
+/// Crates named explicitly plus the ones matched by `--filter`, without duplicates
+fn select_crates<'a>(
+    ws: &'a Workspace,
+    names: &[String],
+    filter: &FilterArgs,
+) -> Result<Vec<&'a Krate>> {
+    let mut crates = names
+        .iter()
+        .map(|name| {
+            ws.crates
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Crate '{}' not found", name))
+        })
+        .collect::<Result<Vec<&Krate>>>()?;
+
+    if !filter.filters.is_empty() {
+        for krate in filter::select_crates(ws, &filter.filters)? {
+            if !crates.iter().any(|selected| selected.name == krate.name) {
+                crates.push(krate);
+            }
+        }
+    }
+
+    Ok(crates)
+}
+
+/// One line summary of a dependency: requirement, source, table and options
+fn describe_dependency(dep: &Krate) -> String {
+    let mut details = vec![];
+    match dep.dep_kind {
+        DependencyKind::Normal => {}
+        DependencyKind::Build => details.push("build".to_string()),
+        DependencyKind::Dev => details.push("dev".to_string()),
+    }
+    if let Some(target) = &dep.target {
+        details.push(target.clone());
+    }
+    if let Some(rename) = &dep.rename {
+        details.push(format!("as {}", rename));
+    }
+    if dep.optional {
+        details.push("optional".to_string());
+    }
+    if !dep.default_features {
+        details.push("no default features".to_string());
+    }
+    if !dep.features.is_empty() {
+        details.push(format!("features: {}", dep.features.join(", ")));
+    }
+
+    let mut line = format!("{} {} - {}", dep.name, dep.version, dep.path);
+    if !details.is_empty() {
+        line.push_str(&format!(" [{}]", details.join("; ")).dimmed().to_string());
+    }
+    line
+}
+
+fn dependency_kind(dev: bool, build: bool) -> DependencyKind {
+    match (dev, build) {
+        (true, _) => DependencyKind::Dev,
+        (_, true) => DependencyKind::Build,
+        _ => DependencyKind::Normal,
+    }
+}
+
+/// Asks a question on the terminal, returning the trimmed answer
+fn prompt(question: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "{} is required when not running in a terminal",
+            question
+        ));
+    }
+
+    print!("{} {} ", question.bold(), "›".dimmed());
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Interactive selection of the crates of a changeset and of their bumps
+fn prompt_bumps(ws: &Workspace) -> Result<BTreeMap<String, Bump>> {
+    let mut names: Vec<&String> = ws.crates.keys().collect();
+    names.sort();
+    for (idx, name) in names.iter().enumerate() {
+        println!("  {:>2}. {}", idx + 1, name);
+    }
+
+    let selection = prompt("Crates to release (numbers or names, comma separated)")?;
+    let mut bumps = BTreeMap::new();
+    for item in selection
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let name = match item.parse::<usize>() {
+            Ok(idx) if (1..=names.len()).contains(&idx) => names[idx - 1].clone(),
+            _ if ws.crates.contains_key(item) => item.to_string(),
+            _ => return Err(anyhow::anyhow!("Crate '{}' not found", item)),
+        };
+
+        let level = prompt(&format!(
+            "Bump for {} (major, minor, patch, prerelease)",
+            name
+        ))?;
+        bumps.insert(name, level.parse()?);
+    }
+
+    Ok(bumps)
+}
+
+/// Loads the layered configuration, only needed by the commands reading `[cmd]`, `[hooks]`
+/// or `[release]`
+async fn load_config(explicit: Option<&Path>) -> Result<Config> {
+    handle_error(
+        Config::load(explicit, &get_current_dir()?).await,
+        "Failed to load the configuration",
+    )
+}
+
 /// Simplified function to get the current directory with error handling
 fn get_current_dir() -> Result<PathBuf> {
     std::env::current_dir().context("Failed to determine the current directory")